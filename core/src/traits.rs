@@ -14,6 +14,20 @@ pub trait Block: Clone {
 	fn parent_id(&self) -> Option<Self::Identifier>;
 }
 
+/// A block that contains a list of extrinsics.
+pub trait ExtrinsicBlock: Block {
+	/// Extrinsic type of the block.
+	type Extrinsic: Clone;
+	/// Stable identifier of an extrinsic, independent of its position in a
+	/// block.
+	type ExtrinsicId: Clone + Eq + hash::Hash;
+
+	/// Get extrinsics of the block.
+	fn extrinsics(&self) -> Vec<Self::Extrinsic>;
+	/// Get the identifier of an extrinsic.
+	fn extrinsic_id(extrinsic: &Self::Extrinsic) -> Self::ExtrinsicId;
+}
+
 /// A value where the key is contained in.
 pub trait Auxiliary<B: Block>: Clone {
 	/// Key type
@@ -53,6 +67,9 @@ impl AsExternalities<dyn NullExternalities> for () {
 }
 
 /// Externalities for reading a key value based storage.
+///
+/// Keys starting with `NAMESPACE_KEY_PREFIX` are reserved for namespaces,
+/// and should only be accessed through `namespace`.
 pub trait StorageExternalities<Error> {
 	/// Read storage value.
 	fn read_storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
@@ -60,6 +77,76 @@ pub trait StorageExternalities<Error> {
 	fn write_storage(&mut self, key: Vec<u8>, value: Vec<u8>);
 	/// Remove storage value.
 	fn remove_storage(&mut self, key: &[u8]);
+	/// Remove all storage values whose key starts with the given prefix.
+	/// Implementations should only visit keys under the prefix, so that
+	/// killing a namespace does not depend on the size of others.
+	fn remove_prefix(&mut self, prefix: &[u8]);
+}
+
+impl<Error> dyn StorageExternalities<Error> {
+	/// Get a storage view scoped to the given namespace.
+	pub fn namespace(&mut self, name: &[u8]) -> StorageNamespace<'_, Error> {
+		StorageNamespace::new(self, name)
+	}
+
+	/// Remove all storage values in the given namespace, leaving other
+	/// namespaces untouched.
+	pub fn kill_namespace(&mut self, name: &[u8]) {
+		self.remove_prefix(&namespace_prefix(name))
+	}
+}
+
+/// Key prefix reserved for storage namespaces.
+pub const NAMESPACE_KEY_PREFIX: &[u8] = b":namespace:";
+
+/// Key prefix of a storage namespace. The name is length-prefixed, so that
+/// no namespace prefix is a prefix of another namespace's.
+pub fn namespace_prefix(name: &[u8]) -> Vec<u8> {
+	let mut prefix = NAMESPACE_KEY_PREFIX.to_vec();
+	prefix.extend_from_slice(&(name.len() as u32).to_le_bytes());
+	prefix.extend_from_slice(name);
+	prefix
+}
+
+/// Storage externalities scoped to a namespace, implemented as a managed
+/// key prefix.
+pub struct StorageNamespace<'a, Error> {
+	storage: &'a mut dyn StorageExternalities<Error>,
+	prefix: Vec<u8>,
+}
+
+impl<'a, Error> StorageNamespace<'a, Error> {
+	/// Create a new storage namespace view.
+	pub fn new(storage: &'a mut dyn StorageExternalities<Error>, name: &[u8]) -> Self {
+		Self { storage, prefix: namespace_prefix(name) }
+	}
+
+	fn prefixed(&self, key: &[u8]) -> Vec<u8> {
+		let mut prefixed = self.prefix.clone();
+		prefixed.extend_from_slice(key);
+		prefixed
+	}
+}
+
+impl<'a, Error> StorageExternalities<Error> for StorageNamespace<'a, Error> {
+	fn read_storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+		self.storage.read_storage(&self.prefixed(key))
+	}
+
+	fn write_storage(&mut self, key: Vec<u8>, value: Vec<u8>) {
+		let key = self.prefixed(&key);
+		self.storage.write_storage(key, value)
+	}
+
+	fn remove_storage(&mut self, key: &[u8]) {
+		let key = self.prefixed(key);
+		self.storage.remove_storage(&key)
+	}
+
+	fn remove_prefix(&mut self, prefix: &[u8]) {
+		let prefix = self.prefixed(prefix);
+		self.storage.remove_prefix(&prefix)
+	}
 }
 
 /// Block executor
@@ -3,17 +3,37 @@ extern crate parity_codec as codec;
 mod runtime;
 
 use blockchain::backend::{SharedMemoryBackend, KeyValueMemoryState, ChainQuery, ImportOperation, ImportLock};
-use blockchain::import::ImportAction;
-use blockchain::{Block as BlockT, SimpleBuilderExecutor, AsExternalities};
+use blockchain::import::{ImportAction, BlockBuilder, AuthorConfig};
+use blockchain::Block as BlockT;
 use blockchain_network_simple::{BestDepthImporter, BestDepthStatusProducer};
-use std::thread;
+use std::{fs, thread};
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use clap::{App, SubCommand, AppSettings, Arg};
 use crate::runtime::{Block, Executor};
 
 fn main() {
 	let matches = App::new("Blockchain counter demo")
 		.setting(AppSettings::SubcommandRequiredElseHelp)
+		.arg(Arg::with_name("chain-spec")
+			 .long("chain-spec")
+			 .takes_value(true)
+			 .global(true)
+			 .help("Chain spec file with the default authoring settings"))
+		.arg(Arg::with_name("interval")
+			 .long("interval")
+			 .takes_value(true)
+			 .global(true)
+			 .help("Milliseconds between two authoring slots"))
+		.arg(Arg::with_name("no-empty-blocks")
+			 .long("no-empty-blocks")
+			 .global(true)
+			 .help("Skip authoring slots without extrinsics"))
+		.arg(Arg::with_name("keep-alive")
+			 .long("keep-alive")
+			 .takes_value(true)
+			 .global(true)
+			 .help("Milliseconds after which an empty block is authored anyway"))
 		.subcommand(SubCommand::with_name("local")
 					.about("Start a local test network"))
 		.subcommand(SubCommand::with_name("libp2p")
@@ -28,20 +48,47 @@ fn main() {
 						 .help("Whether to author blocks")))
 		.get_matches();
 
-	if let Some(_) = matches.subcommand_matches("local") {
-		local_sync();
+	if let Some(matches) = matches.subcommand_matches("local") {
+		local_sync(author_config(matches));
 		return
 	}
 
 	if let Some(matches) = matches.subcommand_matches("libp2p") {
 		let port = matches.value_of("port").unwrap_or("37365");
 		let author = matches.is_present("author");
-		libp2p_sync(port, author);
+		libp2p_sync(port, author, author_config(matches));
 		return
 	}
 }
 
-fn local_sync() {
+fn author_config(matches: &clap::ArgMatches) -> AuthorConfig {
+	let mut config = match matches.value_of("chain-spec") {
+		Some(path) => {
+			let spec = fs::read_to_string(path).expect("Cannot read chain spec");
+			AuthorConfig::from_chain_spec(&spec).expect("Invalid chain spec")
+		},
+		None => AuthorConfig::default(),
+	};
+
+	// Command line flags override the chain spec.
+	let millis = |name| {
+		matches.value_of(name)
+			.map(|v| Duration::from_millis(v.parse().expect("Invalid duration")))
+	};
+	if let Some(interval) = millis("interval") {
+		config.interval = interval;
+	}
+	if let Some(keep_alive) = millis("keep-alive") {
+		config.keep_alive = keep_alive;
+	}
+	if matches.is_present("no-empty-blocks") {
+		config.no_empty_blocks = true;
+	}
+
+	config
+}
+
+fn local_sync(config: AuthorConfig) {
 	let genesis_block = Block::genesis();
 	let (backend_build, lock_build) = (
 		SharedMemoryBackend::<_, (), KeyValueMemoryState>::new_with_genesis(
@@ -68,13 +115,13 @@ fn local_sync() {
 		peers.insert(peer_id, (backend, lock, importer, status));
 	}
 	thread::spawn(move || {
-		builder_thread(backend_build, lock_build);
+		builder_thread(backend_build, lock_build, config);
 	});
 
 	blockchain_network_simple::local::start_local_simple_sync(peers);
 }
 
-fn libp2p_sync(port: &str, author: bool) {
+fn libp2p_sync(port: &str, author: bool, config: AuthorConfig) {
 	let genesis_block = Block::genesis();
 	let backend = SharedMemoryBackend::<_, (), KeyValueMemoryState>::new_with_genesis(
 		genesis_block.clone(),
@@ -87,28 +134,38 @@ fn libp2p_sync(port: &str, author: bool) {
 		let backend_build = backend.clone();
 		let lock_build = lock.clone();
 		thread::spawn(move || {
-			builder_thread(backend_build, lock_build);
+			builder_thread(backend_build, lock_build, config);
 		});
 	}
 	blockchain_network_simple::libp2p::start_network_simple_sync(port, backend, lock, importer, status);
 }
 
-fn builder_thread(backend_build: SharedMemoryBackend<Block, (), KeyValueMemoryState>, lock: ImportLock) {
+fn builder_thread(
+	backend_build: SharedMemoryBackend<Block, (), KeyValueMemoryState>,
+	lock: ImportLock,
+	config: AuthorConfig,
+) {
+	let mut last_block = Instant::now();
+	let mut wait = config.interval;
+
 	loop {
+		thread::sleep(wait);
+
+		// The counter example has no transaction pool, so every block is empty.
+		let since_last_block = last_block.elapsed();
+		if !config.should_author(since_last_block, 0) {
+			wait = config.idle_wait(since_last_block);
+			continue
+		}
+		wait = config.interval;
+
 		let head = backend_build.head();
 		let executor = Executor;
 		println!("Building on top of {}", head);
 
 		// Build a block.
-		let parent_block = backend_build.block_at(&head).unwrap();
-		let mut pending_state = backend_build.state_at(&head).unwrap();
-
-		let mut unsealed_block = executor.initialize_block(
-			&parent_block, pending_state.as_externalities(), ()
-		).unwrap();
-		executor.finalize_block(
-			&mut unsealed_block, pending_state.as_externalities(),
-		).unwrap();
+		let builder = BlockBuilder::new(&backend_build, &executor, &head, ()).unwrap();
+		let (unsealed_block, pending_state) = builder.finalize().unwrap();
 
 		let block = unsealed_block.seal();
 
@@ -119,5 +176,6 @@ fn builder_thread(backend_build: SharedMemoryBackend<Block, (), KeyValueMemorySt
 		build_importer.import_raw(op);
 		build_importer.set_head(new_block_hash);
 		build_importer.commit().unwrap();
+		last_block = Instant::now();
 	}
 }
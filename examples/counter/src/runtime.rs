@@ -1,6 +1,6 @@
 use primitive_types::H256;
 use blockchain::{
-	Block as BlockT, BlockExecutor,
+	Block as BlockT, ExtrinsicBlock, BlockExecutor,
 	SimpleBuilderExecutor, StorageExternalities,
 };
 use codec::{Encode, Decode};
@@ -64,6 +64,19 @@ impl BlockT for Block {
 	}
 }
 
+impl ExtrinsicBlock for Block {
+	type Extrinsic = Extrinsic;
+	type ExtrinsicId = H256;
+
+	fn extrinsics(&self) -> Vec<Extrinsic> {
+		self.extrinsics.clone()
+	}
+
+	fn extrinsic_id(extrinsic: &Extrinsic) -> H256 {
+		H256::from_slice(Sha3_256::digest(&extrinsic.encode()).as_slice())
+	}
+}
+
 #[derive(Clone, Debug, Encode, Decode)]
 pub enum Extrinsic {
 	Add(u128),
@@ -96,7 +109,7 @@ pub struct Executor;
 impl Executor {
 	fn read_counter(&self, state: &mut <Self as BlockExecutor>::Externalities) -> Result<u128, Error> {
 		Ok(
-			match state.read_storage(b"counter").expect("Error is infallible; qed") {
+			match state.namespace(b"counter").read_storage(b"value").expect("Error is infallible; qed") {
 				Some(counter) => {
 					u128::decode(&mut counter.as_slice()).ok_or(Error::StateCorruption)?
 				},
@@ -106,7 +119,7 @@ impl Executor {
 	}
 
 	fn write_counter(&self, counter: u128, state: &mut <Self as BlockExecutor>::Externalities) {
-		state.write_storage(b"counter".to_vec(), counter.encode());
+		state.namespace(b"counter").write_storage(b"value".to_vec(), counter.encode());
 	}
 }
 
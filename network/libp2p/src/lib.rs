@@ -1,6 +1,7 @@
 use core::fmt::Debug;
 use core::time::Duration;
 use core::ops::DerefMut;
+use std::sync::Arc;
 use parity_codec::{Encode, Decode};
 use libp2p::{identity, NetworkBehaviour, PeerId};
 use libp2p::mdns::Mdns;
@@ -11,7 +12,7 @@ use futures::{Async, stream::Stream};
 use tokio_io::{AsyncRead, AsyncWrite};
 use tokio_timer::Interval;
 use blockchain::backend::{SharedCommittable, ChainQuery, ImportLock};
-use blockchain::import::BlockImporter;
+use blockchain::import::ImportQueue;
 use blockchain_network::{NetworkEnvironment, NetworkHandle, NetworkEvent};
 use blockchain_network::sync::{NetworkSyncMessage, NetworkSync, StatusProducer};
 
@@ -98,16 +99,15 @@ impl<TSubstream: AsyncRead + AsyncWrite, B, S> NetworkBehaviourEventProcess<libp
     }
 }
 
-pub fn start_network_simple_sync<Ba, I, St>(
+pub fn start_network_simple_sync<Ba, St>(
 	port: &str,
 	backend: Ba,
 	import_lock: ImportLock,
-	importer: I,
+	importer: Arc<dyn ImportQueue<Ba::Block>>,
 	status: St,
 ) where
 	Ba: SharedCommittable + ChainQuery + Send + Sync + 'static,
 	Ba::Block: Debug + Encode + Decode + Send + Sync,
-	I: BlockImporter<Block=Ba::Block> + Send + Sync + 'static,
 	St: StatusProducer + Send + Sync + 'static,
 	St::Status: Debug + Clone + Send + Sync,
 {
@@ -5,7 +5,7 @@ use std::sync::{Arc, mpsc::{SyncSender, Receiver, sync_channel}};
 use core::hash::Hash;
 use core::fmt::Debug;
 use blockchain::backend::{SharedCommittable, ChainQuery, ImportLock};
-use blockchain::import::BlockImporter;
+use blockchain::import::ImportQueue;
 use blockchain_network::{NetworkEnvironment, NetworkHandle, NetworkEvent};
 use blockchain_network::sync::{NetworkSync, NetworkSyncMessage, StatusProducer};
 
@@ -47,19 +47,18 @@ impl<P: Eq + Hash + Clone, B: Clone, S: Clone> NetworkHandle for LocalNetworkHan
 	}
 }
 
-pub fn start_local_simple_peer<P, Ba, I, St>(
+pub fn start_local_simple_peer<P, Ba, St>(
 	mut handle: LocalNetworkHandle<P, Ba::Block, St::Status>,
 	receiver: Receiver<(P, NetworkSyncMessage<Ba::Block, St::Status>)>,
 	peer_id: P,
 	backend: Ba,
 	import_lock: ImportLock,
-	importer: I,
+	importer: Arc<dyn ImportQueue<Ba::Block>>,
 	status: St,
 ) -> JoinHandle<()> where
 	P: Debug + Eq + Hash + Clone + Send + Sync + 'static,
 	Ba: SharedCommittable + ChainQuery + Send + Sync + 'static,
 	Ba::Block: Debug + Send + Sync,
-	I: BlockImporter<Block=Ba::Block> + Send + Sync + 'static,
 	St: StatusProducer + Send + Sync + 'static,
 	St::Status: Clone + Debug + Send + Sync,
 {
@@ -81,18 +80,17 @@ pub fn start_local_simple_peer<P, Ba, I, St>(
 	})
 }
 
-pub fn start_local_simple_sync<P, Ba, I, St>(
-	peers: HashMap<P, (Ba, ImportLock, I, St)>
+pub fn start_local_simple_sync<P, Ba, St>(
+	peers: HashMap<P, (Ba, ImportLock, Arc<dyn ImportQueue<Ba::Block>>, St)>
 ) where
 	P: Debug + Eq + Hash + Clone + Send + Sync + 'static,
 	Ba: SharedCommittable + ChainQuery + Send + Sync + 'static,
 	Ba::Block: Debug + Send + Sync,
-	I: BlockImporter<Block=Ba::Block> + Send + Sync + 'static,
 	St: StatusProducer + Send + Sync + 'static,
 	St::Status: Clone + Debug + Send + Sync,
 {
 	let mut senders: HashMap<P, SyncSender<(P, NetworkSyncMessage<Ba::Block, St::Status>)>> = HashMap::new();
-	let mut peers_with_receivers: HashMap<P, (Ba, ImportLock, I, St, Receiver<(P, NetworkSyncMessage<Ba::Block, St::Status>)>)> = HashMap::new();
+	let mut peers_with_receivers: HashMap<P, (Ba, ImportLock, Arc<dyn ImportQueue<Ba::Block>>, St, Receiver<(P, NetworkSyncMessage<Ba::Block, St::Status>)>)> = HashMap::new();
 	for (peer_id, (backend, import_lock, importer, status)) in peers {
 		let (sender, receiver) = sync_channel(10);
 		senders.insert(peer_id.clone(), sender);
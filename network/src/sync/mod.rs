@@ -1,18 +1,48 @@
 use core::task::{Context, Poll, Waker};
 use core::pin::Pin;
 use core::hash::Hash;
-use core::mem;
 use core::time::Duration;
+use core::ops::BitOr;
 use std::collections::{HashMap, VecDeque};
-use blockchain::import::BlockImporter;
+use std::sync::Arc;
+use std::time::{SystemTime, Instant};
+use blockchain::import::{ImportQueue, BlockOrigin};
 use futures::{Stream, StreamExt};
 use futures_timer::Interval;
 use log::*;
 use rand::seq::IteratorRandom;
 
+/// Roles a node takes in the network.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Roles(u8);
+
+impl Roles {
+	/// Full node, able to serve blocks.
+	pub const FULL: Roles = Roles(0b001);
+	/// Light node.
+	pub const LIGHT: Roles = Roles(0b010);
+	/// Authority node.
+	pub const AUTHORITY: Roles = Roles(0b100);
+
+	/// Whether all of the given roles are included.
+	pub fn contains(self, other: Roles) -> bool {
+		self.0 & other.0 == other.0
+	}
+}
+
+impl BitOr for Roles {
+	type Output = Roles;
+
+	fn bitor(self, other: Roles) -> Roles {
+		Roles(self.0 | other.0)
+	}
+}
+
 pub struct PeerStatus<H> {
 	head_status: Option<(H, usize)>,
 	pending_request: Option<usize>,
+	clock_offset: Option<i128>,
+	roles: Roles,
 }
 
 impl<H> Default for PeerStatus<H> {
@@ -20,7 +50,74 @@ impl<H> Default for PeerStatus<H> {
 		Self {
 			head_status: None,
 			pending_request: None,
+			clock_offset: None,
+			roles: Roles::FULL,
+		}
+	}
+}
+
+impl<H> PeerStatus<H> {
+	/// Last known head status of the peer.
+	pub fn head(&self) -> Option<&H> {
+		self.head_status.as_ref().map(|h| &h.0)
+	}
+
+	/// Roles of the peer.
+	pub fn roles(&self) -> Roles {
+		self.roles
+	}
+}
+
+/// Strategy selecting the peer to request blocks from.
+pub trait PeerSelector<P, H> {
+	/// Select a peer among candidates.
+	fn select<'a>(
+		&mut self,
+		peers: impl Iterator<Item=(&'a P, &'a PeerStatus<H>)>,
+	) -> Option<&'a P> where
+		P: 'a,
+		H: 'a;
+}
+
+/// Select a random peer.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomSelector;
+
+impl<P, H> PeerSelector<P, H> for RandomSelector {
+	fn select<'a>(
+		&mut self,
+		peers: impl Iterator<Item=(&'a P, &'a PeerStatus<H>)>,
+	) -> Option<&'a P> where
+		P: 'a,
+		H: 'a,
+	{
+		peers.map(|(peer, _)| peer).choose(&mut rand::thread_rng())
+	}
+}
+
+/// Select the peer with the best head status.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FurthestAheadSelector;
+
+impl<P, H: PartialOrd> PeerSelector<P, H> for FurthestAheadSelector {
+	fn select<'a>(
+		&mut self,
+		peers: impl Iterator<Item=(&'a P, &'a PeerStatus<H>)>,
+	) -> Option<&'a P> where
+		P: 'a,
+		H: 'a,
+	{
+		let mut best: Option<(&'a P, &'a H)> = None;
+
+		for (peer, status) in peers {
+			if let Some(head) = status.head() {
+				if best.map(|(_, best_head)| head > best_head).unwrap_or(true) {
+					best = Some((peer, head));
+				}
+			}
 		}
+
+		best.map(|(peer, _)| peer)
 	}
 }
 
@@ -29,6 +126,13 @@ pub enum SyncEvent<P> {
 	QueryStatus,
 	QueryPeerStatus(P),
 	QueryBlocks(P),
+	/// A peer is ahead of the local head, and sync has started.
+	SyncStarted,
+	/// The local head has reached the sync target, which is the best head of
+	/// full peers. If those peers go away, the target moves to the best
+	/// remaining full peer ahead of the local head, or is kept while there is
+	/// none.
+	SyncCompleted { duration: Duration },
 }
 
 #[derive(PartialEq, Eq)]
@@ -36,43 +140,83 @@ pub struct SyncConfig {
 	pub peer_update_frequency: usize,
 	pub update_frequency: usize,
 	pub request_timeout: usize,
+	/// Number of ticks before the peer to sync from is selected again.
+	pub peer_selection_interval: usize,
+	/// Maximum tolerated offset between the local clock and the median
+	/// clock of peers.
+	pub max_clock_drift: Duration,
+	/// Minimum number of peers reporting their clock before the median is
+	/// considered. Use `MIN_CLOCK_PEERS` unless more peers are wanted; lower
+	/// values are raised to it by `NetworkSync::new`.
+	pub min_clock_peers: usize,
 }
 
-pub struct NetworkSync<P, H, I: BlockImporter> {
+/// Lowest accepted `SyncConfig::min_clock_peers`, so that a single peer
+/// cannot trigger the clock drift warning.
+pub const MIN_CLOCK_PEERS: usize = 3;
+
+pub struct NetworkSync<P, H, B, S = RandomSelector> {
 	head_status: (H, usize),
 	tick: usize,
 	peers: HashMap<P, PeerStatus<H>>,
-	pending_blocks: Vec<I::Block>,
-	importer: I,
+	importer: Arc<dyn ImportQueue<B>>,
 	waker: Option<Waker>,
 	timer: Interval,
 	pending_events: VecDeque<SyncEvent<P>>,
 	last_sync: Option<usize>,
 	config: SyncConfig,
+	selector: S,
+	selected_peer: Option<(P, usize)>,
+	sync_started: Option<(Instant, H)>,
+	clock_drifted: bool,
 }
 
-impl<P, H, I> NetworkSync<P, H, I> where
-	I: BlockImporter,
+impl<P, H, B, S> NetworkSync<P, H, B, S> where
 	P: PartialEq + Eq + Hash,
 	H: PartialOrd,
+	S: PeerSelector<P, H>,
 {
-	pub fn new(head: H, importer: I, tick_duration: Duration, config: SyncConfig) -> Self {
+	pub fn new(
+		head: H,
+		importer: Arc<dyn ImportQueue<B>>,
+		tick_duration: Duration,
+		mut config: SyncConfig,
+		selector: S,
+	) -> Self {
+		if config.min_clock_peers < MIN_CLOCK_PEERS {
+			warn!(
+				"min_clock_peers {} is below the minimum, using {}",
+				config.min_clock_peers, MIN_CLOCK_PEERS,
+			);
+			config.min_clock_peers = MIN_CLOCK_PEERS;
+		}
+
 		Self {
 			head_status: (head, 0),
 			tick: 0,
 			peers: HashMap::new(),
-			pending_blocks: Vec::new(),
 			importer,
 			waker: None,
 			timer: Interval::new(tick_duration),
 			pending_events: VecDeque::new(),
 			last_sync: None,
 			config,
+			selector,
+			selected_peer: None,
+			sync_started: None,
+			clock_drifted: false,
 		}
 	}
 
-	pub fn note_blocks(&mut self, mut blocks: Vec<I::Block>, _source: Option<P>) {
-		self.pending_blocks.append(&mut blocks);
+	pub fn note_blocks(&mut self, blocks: Vec<B>, source: Option<P>) {
+		let origin = match source {
+			Some(_) => BlockOrigin::Network,
+			None => BlockOrigin::Local,
+		};
+
+		for block in blocks {
+			self.importer.enqueue(block, origin);
+		}
 		self.wake();
 	}
 
@@ -82,6 +226,69 @@ impl<P, H, I> NetworkSync<P, H, I> where
 			.head_status = Some((status, self.tick));
 	}
 
+	/// Note the roles of a peer. Peers are assumed to be full nodes until
+	/// noted otherwise.
+	pub fn note_peer_roles(&mut self, peer: P, roles: Roles) {
+		self.peers.entry(peer)
+			.or_default()
+			.roles = roles;
+	}
+
+	pub fn peer_roles(&self, peer: &P) -> Option<Roles> {
+		self.peers.get(peer).map(|status| status.roles)
+	}
+
+	/// Note the local time reported by a peer, as duration since Unix epoch.
+	/// This is only used to warn about local clock drift, and never adjusts
+	/// any timestamp.
+	pub fn note_peer_time(&mut self, peer: P, time: Duration) {
+		let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+			.unwrap_or_default();
+
+		self.peers.entry(peer)
+			.or_default()
+			.clock_offset = Some(time.as_millis() as i128 - now.as_millis() as i128);
+	}
+
+	/// Median offset in milliseconds of peer clocks against the local clock,
+	/// if enough peers have reported their time.
+	pub fn median_clock_offset(&self) -> Option<i128> {
+		let mut offsets = self.peers.values()
+			.filter_map(|status| status.clock_offset)
+			.collect::<Vec<_>>();
+
+		if offsets.len() < self.config.min_clock_peers {
+			return None
+		}
+
+		offsets.sort();
+		Some(offsets[offsets.len() / 2])
+	}
+
+	/// Whether the local clock deviates from the peer median by more than
+	/// the configured drift tolerance.
+	pub fn is_clock_drifted(&self) -> bool {
+		self.median_clock_offset()
+			.map(|offset| offset.unsigned_abs() > self.config.max_clock_drift.as_millis())
+			.unwrap_or(false)
+	}
+
+	/// Warn when the local clock starts drifting. Returns whether a warning
+	/// was emitted.
+	fn check_clock_drift(&mut self) -> bool {
+		let drifted = self.is_clock_drifted();
+		let newly_drifted = drifted && !self.clock_drifted;
+		self.clock_drifted = drifted;
+
+		if newly_drifted {
+			warn!(
+				"Local clock deviates from the median of peers by {} ms; check the system time",
+				self.median_clock_offset().unwrap_or_default(),
+			);
+		}
+		newly_drifted
+	}
+
 	pub fn note_status(&mut self, status: H) {
 		self.head_status = (status, self.tick);
 	}
@@ -91,18 +298,36 @@ impl<P, H, I> NetworkSync<P, H, I> where
 	}
 
 	pub fn note_disconnected(&mut self, peer: P) {
+		if self.selected_peer.as_ref().map(|(selected, _)| selected == &peer).unwrap_or(false) {
+			self.selected_peer = None;
+		}
 		self.peers.remove(&peer);
 	}
 
+	/// Whether a full peer, which blocks can be requested from, is ahead of
+	/// the local head.
 	pub fn is_syncing(&self) -> bool {
-		for (_, peer_status) in &self.peers {
+		self.best_full_head().is_some()
+	}
+
+	/// Best head reported by full peers, if it is ahead of the local head.
+	fn best_full_head(&self) -> Option<&H> {
+		let mut best: Option<&H> = None;
+
+		for peer_status in self.peers.values() {
+			if !peer_status.roles.contains(Roles::FULL) {
+				continue
+			}
+
 			if let Some(peer_head_status) = peer_status.head_status.as_ref() {
-				if peer_head_status.0 > self.head_status.0 {
-					return true
+				if peer_head_status.0 > self.head_status.0 &&
+					best.map(|best| peer_head_status.0 > *best).unwrap_or(true)
+				{
+					best = Some(&peer_head_status.0);
 				}
 			}
 		}
-		false
+		best
 	}
 
 	fn wake(&mut self) {
@@ -118,55 +343,23 @@ impl<P, H, I> NetworkSync<P, H, I> where
 	}
 }
 
-impl<P, H, I> Stream for NetworkSync<P, H, I> where
+impl<P, H, B, S> Stream for NetworkSync<P, H, B, S> where
 	P: PartialEq + Eq + Hash + Clone + Unpin,
-	H: PartialOrd + Unpin,
-	I: BlockImporter + Unpin,
-	I::Block: Clone + Unpin,
-	I::Error: core::fmt::Debug,
+	H: PartialOrd + Clone + Unpin,
+	S: PeerSelector<P, H> + Unpin,
 {
 	type Item = SyncEvent<P>;
 
 	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
 		self.waker = Some(cx.waker().clone());
 
-		let mut pending_blocks = Vec::new();
-		mem::swap(&mut self.pending_blocks, &mut pending_blocks);
-		let mut pending_blocks = pending_blocks.into_iter().map(|v| Some(v)).collect::<Vec<_>>();
-
-		loop {
-			let mut progress = false;
-
-			for block in &mut pending_blocks {
-				if let Some(sblock) = block {
-					match self.importer.import_block(sblock.clone()) {
-						Ok(()) => {
-							*block = None;
-							progress = true;
-							trace!("Imported one block");
-						},
-						Err(e) => {
-							warn!("Import block failed: {:?}", e);
-						},
-					}
-				}
-			}
-
-			if !progress {
-				break
-			}
-		}
-
-		let unimported_blocks = pending_blocks.iter().filter(|v| v.is_some()).count();
-		if unimported_blocks != 0 {
-			warn!("{} blocks cannot be imported", unimported_blocks);
-		}
-
+		let mut ticked = false;
 		loop {
 			match self.timer.poll_next_unpin(cx) {
 				Poll::Pending => break,
 				Poll::Ready(Some(())) => {
 					self.tick += 1;
+					ticked = true;
 				},
 				Poll::Ready(None) => {
 					return Poll::Ready(None)
@@ -174,6 +367,15 @@ impl<P, H, I> Stream for NetworkSync<P, H, I> where
 			}
 		}
 
+		let orphan_blocks = self.importer.pending();
+		if ticked && orphan_blocks != 0 {
+			warn!("{} blocks are waiting for their parent", orphan_blocks);
+		}
+
+		if ticked {
+			self.check_clock_drift();
+		}
+
 		let mut new_events = Vec::new();
 		let current_tick = self.tick;
 		let request_timeout = self.config.request_timeout;
@@ -200,23 +402,51 @@ impl<P, H, I> Stream for NetworkSync<P, H, I> where
 			}
 		}
 
-		if self.is_syncing() {
-			let mut need_initialize_new_request = false;
-
-			for (_, status) in &self.peers {
-				if status.head_status.as_ref().map(|h| h.0 > self.head_status.0).unwrap_or(false) {
-					need_initialize_new_request = true;
+		let best_head = self.best_full_head().cloned();
+		let is_syncing = best_head.is_some();
+		match self.sync_started.take() {
+			None => if let Some(target) = best_head {
+				self.sync_started = Some((Instant::now(), target));
+				new_events.push(SyncEvent::SyncStarted);
+			},
+			Some((started, target)) => {
+				let target = best_head.unwrap_or(target);
+				if self.head_status.0 >= target {
+					new_events.push(SyncEvent::SyncCompleted { duration: started.elapsed() });
+				} else {
+					self.sync_started = Some((started, target));
 				}
-			}
+			},
+		}
 
-			need_initialize_new_request = need_initialize_new_request && self.last_sync.map(|l| {
+		if is_syncing {
+			let need_initialize_new_request = self.last_sync.map(|l| {
 				current_tick - l >= update_frequency
 			}).unwrap_or(true);
 
 			if need_initialize_new_request {
-				if let Some((peer, status)) = self.peers.iter_mut().choose(&mut rand::thread_rng()) {
-					new_events.push(SyncEvent::QueryBlocks(peer.clone()));
-					status.pending_request = Some(current_tick);
+				let this = &mut *self;
+				let peer_selection_interval = this.config.peer_selection_interval;
+				let selected = match this.selected_peer.take() {
+					Some((peer, selected_tick)) if
+						current_tick - selected_tick < peer_selection_interval &&
+						this.peers.get(&peer)
+							.map(|status| status.roles.contains(Roles::FULL) && status.head_status.as_ref()
+								.map(|head_status| head_status.0 > this.head_status.0)
+								.unwrap_or(false))
+							.unwrap_or(false)
+					=> Some((peer, selected_tick)),
+					_ => this.selector.select(
+						this.peers.iter().filter(|(_, status)| status.roles.contains(Roles::FULL))
+					).map(|peer| (peer.clone(), current_tick)),
+				};
+				this.selected_peer = selected.clone();
+
+				if let Some((peer, _)) = selected {
+					if let Some(status) = this.peers.get_mut(&peer) {
+						status.pending_request = Some(current_tick);
+					}
+					new_events.push(SyncEvent::QueryBlocks(peer));
 				}
 
 				self.last_sync = Some(self.tick);
@@ -234,3 +464,246 @@ impl<P, H, I> Stream for NetworkSync<P, H, I> where
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Mutex;
+	use futures::task::noop_waker;
+
+	#[derive(Default)]
+	struct TestQueue(Mutex<Vec<u64>>);
+
+	impl ImportQueue<u64> for TestQueue {
+		fn enqueue(&self, block: u64, _origin: BlockOrigin) {
+			self.0.lock().expect("Lock is poisoned").push(block);
+		}
+
+		fn pending(&self) -> usize {
+			0
+		}
+	}
+
+	fn sync(min_clock_peers: usize) -> NetworkSync<u64, usize, u64> {
+		NetworkSync::new(
+			0,
+			Arc::new(TestQueue::default()),
+			Duration::from_secs(3600),
+			SyncConfig {
+				peer_update_frequency: 1,
+				update_frequency: 1,
+				request_timeout: 1,
+				peer_selection_interval: 1,
+				max_clock_drift: Duration::from_secs(1),
+				min_clock_peers,
+			},
+			RandomSelector,
+		)
+	}
+
+	fn now() -> Duration {
+		SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap()
+	}
+
+	#[test]
+	fn single_peer_does_not_report_drift() {
+		let mut sync = sync(MIN_CLOCK_PEERS);
+		sync.note_peer_time(1, now() + Duration::from_secs(60));
+
+		assert_eq!(sync.median_clock_offset(), None);
+		assert!(!sync.is_clock_drifted());
+	}
+
+	#[test]
+	fn too_few_clock_peers_are_raised_to_minimum() {
+		let mut sync = sync(1);
+		sync.note_peer_time(1, now() + Duration::from_secs(60));
+		sync.note_peer_time(2, now() + Duration::from_secs(60));
+
+		assert_eq!(sync.config.min_clock_peers, MIN_CLOCK_PEERS);
+		assert_eq!(sync.median_clock_offset(), None);
+	}
+
+	#[test]
+	fn median_ignores_outliers() {
+		let mut sync = sync(3);
+		sync.note_peer_time(1, now() + Duration::from_secs(600));
+		sync.note_peer_time(2, now());
+		sync.note_peer_time(3, now());
+
+		assert!(sync.median_clock_offset().unwrap().abs() < 1_000);
+		assert!(!sync.is_clock_drifted());
+	}
+
+	#[test]
+	fn drift_warning_is_emitted_once() {
+		let mut sync = sync(3);
+		for peer in 1..=3 {
+			sync.note_peer_time(peer, now() + Duration::from_secs(60));
+		}
+
+		assert!(sync.is_clock_drifted());
+		assert!(sync.check_clock_drift());
+		assert!(!sync.check_clock_drift());
+
+		for peer in 1..=3 {
+			sync.note_peer_time(peer, now());
+		}
+		assert!(!sync.check_clock_drift());
+
+		for peer in 1..=3 {
+			sync.note_peer_time(peer, now() - Duration::from_secs(60));
+		}
+		assert!(sync.check_clock_drift());
+	}
+
+	fn drain(sync: &mut NetworkSync<u64, usize, u64>) -> Vec<SyncEvent<u64>> {
+		let waker = noop_waker();
+		let mut cx = Context::from_waker(&waker);
+		let mut events = Vec::new();
+		while let Poll::Ready(Some(event)) = Pin::new(&mut *sync).poll_next(&mut cx) {
+			events.push(event);
+		}
+		events
+	}
+
+	#[test]
+	fn peers_are_full_until_noted_otherwise() {
+		let mut sync = sync(3);
+		sync.note_connected(1);
+		assert_eq!(sync.peer_roles(&1), Some(Roles::FULL));
+
+		sync.note_peer_roles(1, Roles::LIGHT | Roles::AUTHORITY);
+		assert_eq!(sync.peer_roles(&1), Some(Roles::LIGHT | Roles::AUTHORITY));
+		assert_eq!(sync.peer_roles(&2), None);
+	}
+
+	#[test]
+	fn blocks_are_only_requested_from_full_peers() {
+		let mut light_only = sync(3);
+		light_only.note_peer_status(1, 10);
+		light_only.note_peer_roles(1, Roles::LIGHT);
+		assert!(!light_only.is_syncing());
+		assert!(!drain(&mut light_only).into_iter().any(|e| matches!(
+			e, SyncEvent::QueryBlocks(_) | SyncEvent::SyncStarted
+		)));
+
+		let mut mixed = sync(3);
+		mixed.note_peer_status(1, 10);
+		mixed.note_peer_roles(1, Roles::LIGHT);
+		mixed.note_peer_status(2, 5);
+		mixed.note_peer_roles(2, Roles::FULL | Roles::AUTHORITY);
+		let requested = drain(&mut mixed).into_iter()
+			.filter_map(|e| match e {
+				SyncEvent::QueryBlocks(peer) => Some(peer),
+				_ => None,
+			})
+			.collect::<Vec<_>>();
+		assert_eq!(requested, vec![2]);
+	}
+
+	fn has_started(events: &[SyncEvent<u64>]) -> bool {
+		events.iter().any(|e| matches!(e, SyncEvent::SyncStarted))
+	}
+
+	fn has_completed(events: &[SyncEvent<u64>]) -> bool {
+		events.iter().any(|e| matches!(e, SyncEvent::SyncCompleted { .. }))
+	}
+
+	#[test]
+	fn sync_completes_when_target_is_reached() {
+		let mut sync = sync(3);
+		sync.note_peer_status(1, 10);
+		let events = drain(&mut sync);
+		assert!(has_started(&events));
+		assert!(!has_completed(&events));
+
+		sync.note_status(5);
+		let events = drain(&mut sync);
+		assert!(!has_started(&events));
+		assert!(!has_completed(&events));
+
+		sync.note_status(10);
+		assert!(has_completed(&drain(&mut sync)));
+	}
+
+	#[test]
+	fn lost_target_peer_does_not_complete_sync() {
+		let mut sync = sync(3);
+		sync.note_peer_status(1, 10);
+		assert!(has_started(&drain(&mut sync)));
+
+		sync.note_disconnected(1);
+		assert!(!has_completed(&drain(&mut sync)));
+
+		sync.note_peer_status(2, 20);
+		sync.note_peer_roles(2, Roles::LIGHT);
+		assert!(!has_completed(&drain(&mut sync)));
+
+		sync.note_peer_status(3, 4);
+		let events = drain(&mut sync);
+		assert!(!has_started(&events));
+		assert!(!has_completed(&events));
+
+		sync.note_status(4);
+		assert!(has_completed(&drain(&mut sync)));
+	}
+
+	fn furthest_ahead_sync(peer_selection_interval: usize) -> NetworkSync<u64, usize, u64, FurthestAheadSelector> {
+		NetworkSync::new(
+			0,
+			Arc::new(TestQueue::default()),
+			Duration::from_secs(3600),
+			SyncConfig {
+				peer_update_frequency: 1,
+				update_frequency: 1,
+				request_timeout: 1,
+				peer_selection_interval,
+				max_clock_drift: Duration::from_secs(1),
+				min_clock_peers: MIN_CLOCK_PEERS,
+			},
+			FurthestAheadSelector,
+		)
+	}
+
+	fn requested_peers(sync: &mut NetworkSync<u64, usize, u64, FurthestAheadSelector>) -> Vec<u64> {
+		let waker = noop_waker();
+		let mut cx = Context::from_waker(&waker);
+		let mut peers = Vec::new();
+		while let Poll::Ready(Some(event)) = Pin::new(&mut *sync).poll_next(&mut cx) {
+			if let SyncEvent::QueryBlocks(peer) = event {
+				peers.push(peer);
+			}
+		}
+		peers
+	}
+
+	#[test]
+	fn selected_peer_is_kept_until_interval_expires() {
+		let mut sync = furthest_ahead_sync(10);
+		sync.note_peer_status(1, 10);
+		sync.note_peer_status(2, 5);
+		assert_eq!(requested_peers(&mut sync), vec![1]);
+
+		sync.note_peer_status(2, 50);
+		sync.tick += 1;
+		sync.note_status(0);
+		assert_eq!(requested_peers(&mut sync), vec![1]);
+
+		sync.tick += 10;
+		sync.note_status(0);
+		assert_eq!(requested_peers(&mut sync), vec![2]);
+	}
+
+	#[test]
+	fn selected_peer_is_dropped_once_caught_up() {
+		let mut sync = furthest_ahead_sync(10);
+		sync.note_peer_status(1, 5);
+		assert_eq!(requested_peers(&mut sync), vec![1]);
+
+		sync.note_peer_status(2, 50);
+		sync.tick += 1;
+		sync.note_status(5);
+		assert_eq!(requested_peers(&mut sync), vec![2]);
+	}
+}
@@ -1,8 +1,10 @@
 use std::{fmt, error as stderror};
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
-use crate::{Block, Auxiliary};
-use crate::backend::{Store, BlockData, ChainQuery, ChainSettlement, Operation, Committable, SharedCommittable, OperationError};
+use std::ops::Deref;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::atomic::{AtomicU64, AtomicI64, Ordering};
+use crate::{Block, ExtrinsicBlock, Auxiliary};
+use crate::backend::{OverlayBackend, Store, BlockData, ChainQuery, ChainSettlement, Operation, Committable, SharedCommittable, OperationError, tree_route};
 
 #[derive(Debug)]
 /// Memory errors
@@ -40,6 +42,7 @@ pub struct MemoryDatabase<B: Block, A: Auxiliary<B>, S> {
 	genesis: B::Identifier,
 	canon_depth_mappings: HashMap<usize, B::Identifier>,
 	auxiliaries: HashMap<A::Key, A>,
+	metadata: HashMap<String, Vec<u8>>,
 }
 
 impl<B: Block, A: Auxiliary<B>, S: Clone> Store for MemoryDatabase<B, A, S> {
@@ -214,10 +217,48 @@ impl<B: Block, A: Auxiliary<B>, S: Clone> MemoryBackend<B, A, S> {
 			blocks_and_states,
 			canon_depth_mappings,
 			auxiliaries: Default::default(),
+			metadata: Default::default(),
 			genesis: genesis_id.clone(),
 			head: genesis_id,
 		})
 	}
+
+	/// Set a node metadata value.
+	pub fn set_metadata(&mut self, key: &str, value: &[u8]) -> Result<(), Error> {
+		self.0.metadata.insert(key.to_string(), value.to_vec());
+		Ok(())
+	}
+
+	/// Get a node metadata value.
+	pub fn get_metadata(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+		Ok(self.0.metadata.get(key).cloned())
+	}
+
+	/// Get block metadata, without cloning the block body or state.
+	pub fn block_metadata(&self, hash: &B::Identifier) -> Result<BlockMetadata<B>, Error> {
+		self.0.blocks_and_states.get(hash)
+			.map(|data| BlockMetadata {
+				hash: hash.clone(),
+				parent_hash: data.block.parent_id(),
+				depth: data.depth,
+				is_canon: data.is_canon,
+				children_count: data.children.len(),
+			})
+			.ok_or(Error::NotExist)
+	}
+
+	/// Get up to `n` chain tips with the greatest depth, breaking ties by
+	/// hash ordering.
+	pub fn best_n_tips(&self, n: usize) -> Vec<B::Identifier> where
+		B::Identifier: Ord,
+	{
+		let mut tips = self.0.blocks_and_states.iter()
+			.filter(|(_, data)| data.children.is_empty())
+			.map(|(hash, data)| (data.depth, hash.clone()))
+			.collect::<Vec<_>>();
+		tips.sort_by(|a, b| b.cmp(a));
+		tips.into_iter().take(n).map(|(_, hash)| hash).collect()
+	}
 }
 
 impl<B: Block, A: Auxiliary<B>, S: Clone> Store for MemoryBackend<B, A, S> {
@@ -295,15 +336,186 @@ impl<B: Block, A: Auxiliary<B>, S: Clone> Committable for MemoryBackend<B, A, S>
 	}
 }
 
+/// Metadata of a stored block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockMetadata<B: Block> {
+	/// Block hash.
+	pub hash: B::Identifier,
+	/// Parent block hash. None if the block is genesis.
+	pub parent_hash: Option<B::Identifier>,
+	/// Depth.
+	pub depth: usize,
+	/// Whether the block is on the canonical chain.
+	pub is_canon: bool,
+	/// Number of children of the block.
+	pub children_count: usize,
+}
+
+/// Lock statistics of a shared memory backend.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LockStats {
+	/// Total number of read lock acquisitions.
+	pub read_lock_acquisitions: u64,
+	/// Total number of write lock acquisitions.
+	pub write_lock_acquisitions: u64,
+	/// Number of readers currently holding the lock.
+	pub current_readers: i64,
+}
+
+#[derive(Default)]
+struct LockCounters {
+	read_lock_acquisitions: AtomicU64,
+	write_lock_acquisitions: AtomicU64,
+	current_readers: AtomicI64,
+}
+
+struct ReadGuard<'a, T> {
+	guard: RwLockReadGuard<'a, T>,
+	counters: &'a LockCounters,
+}
+
+impl<'a, T> Deref for ReadGuard<'a, T> {
+	type Target = T;
+
+	fn deref(&self) -> &T {
+		&self.guard
+	}
+}
+
+impl<'a, T> Drop for ReadGuard<'a, T> {
+	fn drop(&mut self) {
+		self.counters.current_readers.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
+/// Blocks reverted and made canonical by a head change.
+#[derive(Clone, Debug)]
+pub struct ReorgEvent<B: Block> {
+	/// Previously canonical blocks, from the old head towards the common
+	/// ancestor.
+	pub reverted: Vec<B::Identifier>,
+	/// Newly canonical blocks, from the common ancestor towards the new head.
+	pub new_canonical: Vec<B::Identifier>,
+}
+
+type ReorgHook<B> = Arc<dyn Fn(ReorgEvent<B>) + Send + Sync>;
+
 /// Shared memory backend
-pub struct SharedMemoryBackend<B: Block, A: Auxiliary<B>, S>(
-	Arc<RwLock<MemoryBackend<B, A, S>>>
-);
+pub struct SharedMemoryBackend<B: Block, A: Auxiliary<B>, S> {
+	backend: Arc<RwLock<MemoryBackend<B, A, S>>>,
+	counters: Arc<LockCounters>,
+	total_blocks_imported: Arc<AtomicU64>,
+	reorg_hook: Arc<RwLock<Option<ReorgHook<B>>>>,
+	best: Arc<RwLock<(B::Identifier, usize)>>,
+	last_imported: Arc<RwLock<Option<B::Identifier>>>,
+}
 
 impl<B: Block, A: Auxiliary<B>, S: Clone> SharedMemoryBackend<B, A, S> {
 	/// Create a new memory backend from genesis.
 	pub fn new_with_genesis(block: B, genesis_state: S) -> Self {
-		Self(Arc::new(RwLock::new(MemoryBackend::new_with_genesis(block, genesis_state))))
+		let genesis_id = block.id();
+
+		Self {
+			backend: Arc::new(RwLock::new(MemoryBackend::new_with_genesis(block, genesis_state))),
+			counters: Default::default(),
+			total_blocks_imported: Default::default(),
+			reorg_hook: Default::default(),
+			best: Arc::new(RwLock::new((genesis_id, 0))),
+			last_imported: Default::default(),
+		}
+	}
+
+	/// Get the best block hash and depth, without acquiring the backend
+	/// lock. Both are updated together on every commit.
+	pub fn best_block(&self) -> (B::Identifier, usize) {
+		self.best.read().expect("Lock is poisoned").clone()
+	}
+
+	/// Set a hook called after every commit that retracts canonical blocks.
+	/// The hook is called without holding the backend lock.
+	pub fn set_reorg_hook<F>(&self, f: F) where
+		F: Fn(ReorgEvent<B>) + Send + Sync + 'static,
+	{
+		*self.reorg_hook.write().expect("Lock is poisoned") = Some(Arc::new(f));
+	}
+
+	/// Get the deepest block of the most recent commit that imported blocks,
+	/// without acquiring the backend lock. None if no block has been
+	/// imported since startup.
+	pub fn last_imported(&self) -> Option<B::Identifier> {
+		self.last_imported.read().expect("Lock is poisoned").clone()
+	}
+
+	/// Get the total number of blocks imported, without acquiring the lock.
+	pub fn total_blocks_imported(&self) -> u64 {
+		self.total_blocks_imported.load(Ordering::Relaxed)
+	}
+
+	/// Set a node metadata value, such as last sync time or chain spec
+	/// version, stored apart from blocks, states and auxiliaries.
+	pub fn set_metadata(&self, key: &str, value: &[u8]) -> Result<(), Error> {
+		self.write().set_metadata(key, value)
+	}
+
+	/// Get a node metadata value.
+	pub fn get_metadata(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+		self.read().get_metadata(key)
+	}
+
+	/// Get block metadata, without cloning the block body or state.
+	pub fn block_metadata(&self, hash: &B::Identifier) -> Result<BlockMetadata<B>, Error> {
+		self.read().block_metadata(hash)
+	}
+
+	/// Check that an operation would commit cleanly, by committing it to
+	/// an overlay that is then discarded. The dry run holds a single read
+	/// lock, so it sees one view of the chain, but a commit right after it
+	/// may still change the outcome.
+	pub fn apply_operation_dry_run(&self, operation: &Operation<B, S, A>) -> Result<(), Error> {
+		let backend = self.read();
+		OverlayBackend::new(&*backend).commit(operation.clone())
+	}
+
+	/// Get up to `n` chain tips with the greatest depth, breaking ties by
+	/// hash ordering.
+	pub fn best_n_tips(&self, n: usize) -> Vec<B::Identifier> where
+		B::Identifier: Ord,
+	{
+		self.read().best_n_tips(n)
+	}
+
+	/// Get extrinsics of a stored block.
+	pub fn block_extrinsics(
+		&self,
+		hash: &B::Identifier,
+	) -> Result<Vec<B::Extrinsic>, Error> where
+		B: ExtrinsicBlock,
+	{
+		Ok(self.read().block_at(hash)?.extrinsics())
+	}
+
+	/// Get lock statistics, to diagnose lock contention.
+	pub fn lock_stats(&self) -> LockStats {
+		LockStats {
+			read_lock_acquisitions: self.counters.read_lock_acquisitions.load(Ordering::Relaxed),
+			write_lock_acquisitions: self.counters.write_lock_acquisitions.load(Ordering::Relaxed),
+			current_readers: self.counters.current_readers.load(Ordering::Relaxed),
+		}
+	}
+
+	fn read(&self) -> ReadGuard<'_, MemoryBackend<B, A, S>> {
+		let guard = self.backend.read().expect("Lock is poisoned");
+		self.counters.read_lock_acquisitions.fetch_add(1, Ordering::Relaxed);
+		self.counters.current_readers.fetch_add(1, Ordering::Relaxed);
+
+		ReadGuard { guard, counters: &self.counters }
+	}
+
+	fn write(&self) -> RwLockWriteGuard<'_, MemoryBackend<B, A, S>> {
+		let guard = self.backend.write().expect("Lock is poisoned");
+		self.counters.write_lock_acquisitions.fetch_add(1, Ordering::Relaxed);
+
+		guard
 	}
 }
 
@@ -316,64 +528,118 @@ impl<B: Block, A: Auxiliary<B>, S: Clone> Store for SharedMemoryBackend<B, A, S>
 
 impl<B: Block, A: Auxiliary<B>, S: Clone> ChainQuery for SharedMemoryBackend<B, A, S> {
 	fn genesis(&self) -> <Self::Block as Block>::Identifier {
-		self.0.read().expect("Lock is poisoned").genesis()
+		self.read().genesis()
 	}
 	fn head(&self) -> <Self::Block as Block>::Identifier {
-		self.0.read().expect("Lock is poisoned").head()
+		self.read().head()
 	}
 	fn contains(
 		&self,
 		hash: &<Self::Block as Block>::Identifier,
 	) -> Result<bool, Self::Error> {
-		Ok(self.0.read().expect("Lock is poisoned").contains(hash)?)
+		Ok(self.read().contains(hash)?)
 	}
 	fn is_canon(
 		&self,
 		hash: &<Self::Block as Block>::Identifier,
 	) -> Result<bool, Self::Error> {
-		Ok(self.0.read().expect("Lock is poisoned").is_canon(hash)?)
+		Ok(self.read().is_canon(hash)?)
 	}
 	fn lookup_canon_depth(
 		&self,
 		depth: usize,
 	) -> Result<Option<<Self::Block as Block>::Identifier>, Self::Error> {
-		Ok(self.0.read().expect("Lock is poisoned").lookup_canon_depth(depth)?)
+		Ok(self.read().lookup_canon_depth(depth)?)
+	}
+	/// Check the whole range under a single read lock, so that a concurrent
+	/// reorg cannot be seen half way.
+	fn depth_range_exists(
+		&self,
+		from: usize,
+		to: usize,
+	) -> Result<bool, Self::Error> {
+		Ok(self.read().depth_range_exists(from, to)?)
 	}
 	fn auxiliary(
 		&self,
 		key: &<Self::Auxiliary as Auxiliary<Self::Block>>::Key,
 	) -> Result<Option<Self::Auxiliary>, Self::Error> {
-		Ok(self.0.read().expect("Lock is poisoned").auxiliary(key)?)
+		Ok(self.read().auxiliary(key)?)
 	}
 	fn depth_at(
 		&self,
 		hash: &<Self::Block as Block>::Identifier,
 	) -> Result<usize, Self::Error> {
-		Ok(self.0.read().expect("Lock is poisoned").depth_at(hash)?)
+		Ok(self.read().depth_at(hash)?)
 	}
 	fn children_at(
 		&self,
 		hash: &<Self::Block as Block>::Identifier,
 	) -> Result<Vec<<Self::Block as Block>::Identifier>, Self::Error> {
-		Ok(self.0.read().expect("Lock is poisoned").children_at(hash)?)
+		Ok(self.read().children_at(hash)?)
 	}
 	fn state_at(
 		&self,
 		hash: &<Self::Block as Block>::Identifier,
 	) -> Result<Self::State, Self::Error> {
-		Ok(self.0.read().expect("Lock is poisoned").state_at(hash)?)
+		Ok(self.read().state_at(hash)?)
 	}
 	fn block_at(
 		&self,
 		hash: &<Self::Block as Block>::Identifier,
 	) -> Result<Self::Block, Self::Error> {
-		Ok(self.0.read().expect("Lock is poisoned").block_at(hash)?)
+		Ok(self.read().block_at(hash)?)
+	}
+	/// Take the canonical hashes of the range under a single read lock, then
+	/// call `f` without holding the lock, so that a slow `f` does not block
+	/// commits. Stops early if a block does not link to the previous one.
+	fn for_each_block<F, E>(
+		&self,
+		from: usize,
+		to: usize,
+		mut f: F,
+	) -> Result<usize, E> where
+		F: FnMut(<Self::Block as Block>::Identifier, Self::Block) -> Result<(), E>,
+		E: From<Self::Error>,
+	{
+		let hashes = {
+			let backend = self.read();
+			let mut hashes = Vec::new();
+			for depth in from..=to {
+				match backend.lookup_canon_depth(depth)? {
+					Some(hash) => hashes.push(hash),
+					None => break,
+				}
+			}
+			hashes
+		};
+
+		let mut visited = 0;
+		let mut parent = None;
+		for hash in hashes {
+			let block = self.block_at(&hash)?;
+			if parent.is_some() && block.parent_id() != parent {
+				break
+			}
+			parent = Some(hash.clone());
+			f(hash, block)?;
+			visited += 1;
+		}
+
+		Ok(visited)
 	}
 }
 
 impl<B: Block, A: Auxiliary<B>, S: Clone> Clone for SharedMemoryBackend<B, A, S> {
 	fn clone(&self) -> Self {
-		Self(self.0.clone())
+		Self {
+			backend: self.backend.clone(),
+			counters: self.counters.clone(),
+			total_blocks_imported: self.total_blocks_imported.clone(),
+			reorg_hook: self.reorg_hook.clone(),
+			best: self.best.clone(),
+			last_imported: self.last_imported.clone(),
+		}
 	}
 }
 
@@ -384,6 +650,289 @@ impl<B: Block, A: Auxiliary<B>, S: Clone> SharedCommittable for SharedMemoryBack
 		&self,
 		operation: Operation<Self::Block, Self::State, Self::Auxiliary>,
 	) -> Result<(), Self::Error> {
-		self.0.write().expect("Lock is poisoned").commit(operation)
+		let imported = operation.import_block.len() as u64;
+		let new_head = operation.set_head.clone();
+		let imported_ids = operation.import_block.iter()
+			.map(|data| data.block.id())
+			.collect::<Vec<_>>();
+		let hook = self.reorg_hook.read().expect("Lock is poisoned").clone();
+
+		let event = {
+			let mut backend = self.write();
+			let old_head = backend.head();
+			backend.commit(operation)?;
+
+			let head = backend.head();
+			let depth = backend.depth_at(&head)
+				.expect("Head is always a stored block; qed");
+			*self.best.write().expect("Lock is poisoned") = (head, depth);
+			let last_imported = imported_ids.into_iter()
+				.map(|id| {
+					let depth = backend.depth_at(&id)
+						.expect("Block was imported by this commit; qed");
+					(depth, id)
+				})
+				.max_by_key(|(depth, _)| *depth);
+			if let Some((_, id)) = last_imported {
+				*self.last_imported.write().expect("Lock is poisoned") = Some(id);
+			}
+
+			match (&hook, new_head) {
+				(Some(_), Some(new_head)) => {
+					let route = tree_route(&*backend, &old_head, &new_head)
+						.expect("Both heads were committed to the backend; qed");
+					Some(ReorgEvent {
+						reverted: route.retracted().to_vec(),
+						new_canonical: route.enacted().to_vec(),
+					})
+				},
+				_ => None,
+			}
+		};
+		self.total_blocks_imported.fetch_add(imported, Ordering::Relaxed);
+
+		if let (Some(hook), Some(event)) = (hook, event) {
+			if !event.reverted.is_empty() {
+				hook(event);
+			}
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::backend::ImportOperation;
+	use crate::test_support::{TestBlock, TestBackend, genesis_backend, test_generator};
+
+	fn traversed_best_block(backend: &TestBackend) -> (u64, usize) {
+		let mut depth = 0;
+		while backend.lookup_canon_depth(depth + 1).unwrap().is_some() {
+			depth += 1;
+		}
+		(backend.lookup_canon_depth(depth).unwrap().unwrap(), depth)
+	}
+
+	fn canon_chain(backend: &TestBackend) -> Vec<u64> {
+		(1..).map(|depth| backend.lookup_canon_depth(depth).unwrap())
+			.take_while(|id| id.is_some())
+			.map(|id| id.unwrap())
+			.collect()
+	}
+
+	#[test]
+	fn cached_best_block_matches_traversal() {
+		let backend = genesis_backend();
+		let mut generator = test_generator(&backend, 0);
+		assert_eq!(backend.best_block(), traversed_best_block(&backend));
+
+		let a = generator.linear("a", 2).unwrap();
+		assert_eq!(backend.best_block(), (a, 2));
+		assert_eq!(backend.best_block(), traversed_best_block(&backend));
+
+		let b = generator.fork_at("b", 0, 3).unwrap();
+		assert_eq!(backend.best_block(), (b, 3));
+		assert_eq!(backend.best_block(), traversed_best_block(&backend));
+
+		generator.extend("c", "a", 1).unwrap();
+		assert_eq!(backend.best_block(), traversed_best_block(&backend));
+	}
+
+	#[test]
+	fn reorg_hook_reports_retracted_blocks() {
+		let backend = genesis_backend();
+		let mut generator = test_generator(&backend, 0);
+		let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+		let hook_events = events.clone();
+		let hook_backend = backend.clone();
+		backend.set_reorg_hook(move |event| {
+			// The hook runs without the backend lock held.
+			assert_eq!(Some(&hook_backend.head()), event.new_canonical.last());
+			hook_events.lock().unwrap().push((event.reverted, event.new_canonical));
+		});
+
+		generator.linear("a", 2).unwrap();
+		let mut reverted = canon_chain(&backend);
+		reverted.reverse();
+		generator.fork_at("b", 0, 3).unwrap();
+		assert_eq!(*events.lock().unwrap(), vec![(reverted, canon_chain(&backend))]);
+	}
+
+	#[test]
+	fn best_n_tips_orders_by_depth_then_hash() {
+		let backend = genesis_backend();
+		let mut generator = test_generator(&backend, 0);
+		let a = generator.linear("a", 2).unwrap();
+		let b = generator.fork_at("b", 0, 3).unwrap();
+		let c = generator.fork_at("c", 0, 2).unwrap();
+
+		let (high, low) = if a > c { (a, c) } else { (c, a) };
+		assert_eq!(backend.best_n_tips(5), vec![b, high, low]);
+		assert_eq!(backend.best_n_tips(1), vec![b]);
+	}
+
+	#[test]
+	fn block_metadata_describes_stored_blocks() {
+		let backend = genesis_backend();
+		let mut generator = test_generator(&backend, 0);
+		generator.linear("a", 3).unwrap();
+		let b = generator.fork_at("b", 1, 1).unwrap();
+		let a1 = backend.lookup_canon_depth(1).unwrap().unwrap();
+
+		assert_eq!(backend.block_metadata(&a1).unwrap(), BlockMetadata {
+			hash: a1,
+			parent_hash: Some(backend.genesis()),
+			depth: 1,
+			is_canon: true,
+			children_count: 2,
+		});
+		assert_eq!(backend.block_metadata(&b).unwrap(), BlockMetadata {
+			hash: b,
+			parent_hash: Some(a1),
+			depth: 2,
+			is_canon: false,
+			children_count: 0,
+		});
+		assert_eq!(backend.block_metadata(&backend.genesis()).unwrap().parent_hash, None);
+	}
+
+	#[test]
+	fn block_metadata_of_unknown_block_does_not_exist() {
+		let backend = genesis_backend();
+		assert!(matches!(backend.block_metadata(&42), Err(Error::NotExist)));
+	}
+
+	#[test]
+	fn metadata_round_trips() {
+		let mut backend = MemoryBackend::<TestBlock, (), ()>::new_with_genesis(TestBlock::genesis(), ());
+		assert_eq!(backend.get_metadata("spec_version").unwrap(), None);
+
+		backend.set_metadata("spec_version", &[1]).unwrap();
+		assert_eq!(backend.get_metadata("spec_version").unwrap(), Some(vec![1]));
+		backend.set_metadata("spec_version", &[2, 3]).unwrap();
+		assert_eq!(backend.get_metadata("spec_version").unwrap(), Some(vec![2, 3]));
+		assert_eq!(backend.get_metadata("last_sync").unwrap(), None);
+	}
+
+	#[test]
+	fn shared_metadata_round_trips() {
+		let backend = genesis_backend();
+		let other = backend.clone();
+		assert_eq!(backend.get_metadata("spec_version").unwrap(), None);
+
+		backend.set_metadata("spec_version", &[1]).unwrap();
+		assert_eq!(other.get_metadata("spec_version").unwrap(), Some(vec![1]));
+		other.set_metadata("spec_version", &[2, 3]).unwrap();
+		assert_eq!(backend.get_metadata("spec_version").unwrap(), Some(vec![2, 3]));
+		assert_eq!(backend.get_metadata("last_sync").unwrap(), None);
+	}
+
+	#[test]
+	fn last_imported_is_deepest_block_of_last_import() {
+		let backend = genesis_backend();
+		assert_eq!(backend.last_imported(), None);
+
+		let a = test_generator(&backend, 0).linear("a", 1).unwrap();
+		assert_eq!(backend.last_imported(), Some(a));
+
+		backend.commit(Operation {
+			import_block: vec![
+				ImportOperation { block: TestBlock::new(3, 2), state: () },
+				ImportOperation { block: TestBlock::new(2, a), state: () },
+			],
+			..Default::default()
+		}).unwrap();
+		assert_eq!(backend.last_imported(), Some(3));
+
+		backend.commit(Operation { set_head: Some(3), ..Default::default() }).unwrap();
+		assert_eq!(backend.last_imported(), Some(3));
+	}
+
+	#[test]
+	fn block_extrinsics_of_stored_blocks() {
+		let backend = genesis_backend();
+		backend.commit(Operation {
+			import_block: vec![ImportOperation {
+				block: TestBlock { extrinsics: vec![7, 8], ..TestBlock::new(1, 0) },
+				state: (),
+			}],
+			..Default::default()
+		}).unwrap();
+
+		assert_eq!(backend.block_extrinsics(&1).unwrap(), vec![7, 8]);
+		assert_eq!(backend.block_extrinsics(&0).unwrap(), Vec::<u64>::new());
+		assert!(matches!(backend.block_extrinsics(&42), Err(Error::NotExist)));
+	}
+
+	#[test]
+	fn dry_run_does_not_write() {
+		let backend = genesis_backend();
+		let a = test_generator(&backend, 0).linear("a", 1).unwrap();
+		let operation = |block: TestBlock| Operation {
+			set_head: Some(block.id),
+			import_block: vec![ImportOperation { block, state: () }],
+			..Default::default()
+		};
+
+		assert!(backend.apply_operation_dry_run(&operation(TestBlock::new(2, a))).is_ok());
+		assert!(backend.apply_operation_dry_run(&operation(TestBlock::new(99, 98))).is_err());
+		assert!(!backend.contains(&2).unwrap());
+		assert_eq!(backend.head(), a);
+		assert_eq!(backend.total_blocks_imported(), 1);
+
+		let reads = backend.lock_stats().read_lock_acquisitions;
+		assert!(backend.apply_operation_dry_run(&operation(TestBlock::new(2, a))).is_ok());
+		assert_eq!(backend.lock_stats().read_lock_acquisitions, reads + 1);
+	}
+
+	#[test]
+	fn for_each_block_reports_visited_blocks() {
+		let backend = genesis_backend();
+		test_generator(&backend, 0).linear("a", 3).unwrap();
+
+		let mut visited = Vec::new();
+		assert_eq!(backend.for_each_block(1, 3, |hash, block| {
+			visited.push((hash, block.parent.unwrap()));
+			Ok::<_, Error>(())
+		}).unwrap(), 3);
+		assert_eq!(visited.iter().map(|(hash, _)| *hash).collect::<Vec<_>>(), canon_chain(&backend));
+		assert_eq!(visited[0].1, backend.genesis());
+
+		assert_eq!(backend.for_each_block(2, 10, |_, _| Ok::<_, Error>(())).unwrap(), 2);
+		assert_eq!(backend.for_each_block(5, 10, |_, _| Ok::<_, Error>(())).unwrap(), 0);
+		assert!(backend.for_each_block(0, 3, |_, _| Err(Error::NotExist)).is_err());
+	}
+
+	#[test]
+	fn for_each_block_releases_lock_before_callback() {
+		let backend = genesis_backend();
+		test_generator(&backend, 0).linear("a", 2).unwrap();
+
+		// Taking the write lock in `f` would deadlock if the walk held the
+		// read lock.
+		assert_eq!(backend.for_each_block(0, 2, |hash, _| {
+			backend.set_metadata("last_exported", &hash.to_le_bytes())
+		}).unwrap(), 3);
+		assert_eq!(
+			backend.get_metadata("last_exported").unwrap(),
+			Some(backend.head().to_le_bytes().to_vec()),
+		);
+	}
+
+	#[test]
+	fn depth_range_exists_stops_at_head() {
+		let backend = genesis_backend();
+		test_generator(&backend, 0).linear("a", 3).unwrap();
+
+		assert!(backend.depth_range_exists(0, 3).unwrap());
+		assert!(backend.depth_range_exists(2, 2).unwrap());
+		assert!(!backend.depth_range_exists(2, 4).unwrap());
+		assert!(!backend.depth_range_exists(4, 4).unwrap());
+
+		let reads = backend.lock_stats().read_lock_acquisitions;
+		assert!(backend.depth_range_exists(0, 3).unwrap());
+		assert_eq!(backend.lock_stats().read_lock_acquisitions, reads + 1);
 	}
 }
@@ -1,16 +1,20 @@
 //! Basic backend definitions and memory backend.
 
+mod export;
 mod memory;
+mod overlay;
 mod route;
 mod traits;
 mod operation;
 mod state;
 
-pub use self::memory::{MemoryBackend, MemoryDatabase, SharedMemoryBackend, Error as MemoryError};
+pub use self::export::{export_csv, ExportError};
+pub use self::memory::{MemoryBackend, MemoryDatabase, SharedMemoryBackend, BlockMetadata, LockStats, ReorgEvent, Error as MemoryError};
+pub use self::overlay::OverlayBackend;
 pub use self::route::{tree_route, TreeRoute};
 pub use self::operation::{BlockData, ImportOperation, Operation};
 pub use self::traits::{Store, ChainQuery, ChainSettlement, OperationError, Committable, SharedCommittable};
-pub use self::state::KeyValueMemoryState;
+pub use self::state::{KeyValueMemoryState, StorageChange};
 
 use std::sync::{Arc, Mutex, MutexGuard};
 
@@ -17,6 +17,7 @@ pub struct BlockData<B: Block, S> {
 }
 
 /// Import operation.
+#[derive(Clone)]
 pub struct ImportOperation<B, S> {
 	/// Block to be imported.
 	pub block: B,
@@ -25,6 +26,7 @@ pub struct ImportOperation<B, S> {
 }
 
 /// Operation for a backend.
+#[derive(Clone)]
 pub struct Operation<B: Block, S, A: Auxiliary<B>> {
 	/// Import operation.
 	pub import_block: Vec<ImportOperation<B, S>>,
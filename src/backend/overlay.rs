@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use crate::{Block, Auxiliary};
+use crate::backend::{
+	Store, BlockData, ChainQuery, ChainSettlement, Committable, Operation,
+	ImportOperation, OperationError,
+};
+
+type IdOf<Ba> = <<Ba as Store>::Block as Block>::Identifier;
+type AuxKeyOf<Ba> = <<Ba as Store>::Auxiliary as Auxiliary<<Ba as Store>::Block>>::Key;
+
+/// Backend layering ephemeral changes on top of an inner backend, without
+/// ever writing to it. Queries check the overlay first, then fall back to
+/// the inner backend.
+pub struct OverlayBackend<Ba: ChainQuery> {
+	inner: Ba,
+	blocks_and_states: HashMap<IdOf<Ba>, BlockData<Ba::Block, Ba::State>>,
+	children: HashMap<IdOf<Ba>, Vec<IdOf<Ba>>>,
+	canon: HashMap<IdOf<Ba>, bool>,
+	canon_depth_mappings: HashMap<usize, Option<IdOf<Ba>>>,
+	auxiliaries: HashMap<AuxKeyOf<Ba>, Option<Ba::Auxiliary>>,
+	head: Option<IdOf<Ba>>,
+}
+
+impl<Ba: ChainQuery> OverlayBackend<Ba> {
+	/// Create a new empty overlay on top of the inner backend.
+	pub fn new(inner: Ba) -> Self {
+		Self {
+			inner,
+			blocks_and_states: HashMap::new(),
+			children: HashMap::new(),
+			canon: HashMap::new(),
+			canon_depth_mappings: HashMap::new(),
+			auxiliaries: HashMap::new(),
+			head: None,
+		}
+	}
+
+	/// Get the inner backend.
+	pub fn inner(&self) -> &Ba {
+		&self.inner
+	}
+
+	/// Discard all changes in the overlay.
+	pub fn discard(&mut self) {
+		self.blocks_and_states.clear();
+		self.children.clear();
+		self.canon.clear();
+		self.canon_depth_mappings.clear();
+		self.auxiliaries.clear();
+		self.head = None;
+	}
+
+	/// Build an operation that applies the overlay to the inner backend.
+	pub fn to_operation(&self) -> Operation<Ba::Block, Ba::State, Ba::Auxiliary> where
+		Ba::State: Clone,
+	{
+		let mut datas = self.blocks_and_states.values().collect::<Vec<_>>();
+		datas.sort_by_key(|data| data.depth);
+
+		let mut insert_auxiliaries = Vec::new();
+		let mut remove_auxiliaries = Vec::new();
+		for (key, value) in &self.auxiliaries {
+			match value {
+				Some(value) => insert_auxiliaries.push(value.clone()),
+				None => remove_auxiliaries.push(key.clone()),
+			}
+		}
+
+		Operation {
+			import_block: datas.into_iter()
+				.map(|data| ImportOperation {
+					block: data.block.clone(),
+					state: data.state.clone(),
+				})
+				.collect(),
+			set_head: self.head.clone(),
+			insert_auxiliaries,
+			remove_auxiliaries,
+		}
+	}
+
+	/// Build an operation that applies only the overlay blocks on the route
+	/// to the given block, and sets it as the head of the inner backend.
+	/// Other overlay blocks, and overlay auxiliaries, are left out.
+	pub fn to_operation_for(
+		&self,
+		id: &IdOf<Ba>,
+	) -> Operation<Ba::Block, Ba::State, Ba::Auxiliary> where
+		Ba::State: Clone,
+	{
+		let mut import_block = Vec::new();
+		let mut current = Some(id.clone());
+		while let Some(data) = current.and_then(|id| self.blocks_and_states.get(&id)) {
+			import_block.push(ImportOperation {
+				block: data.block.clone(),
+				state: data.state.clone(),
+			});
+			current = data.block.parent_id();
+		}
+		import_block.reverse();
+
+		Operation {
+			import_block,
+			set_head: Some(id.clone()),
+			..Default::default()
+		}
+	}
+}
+
+impl<Ba: ChainQuery> Store for OverlayBackend<Ba> {
+	type Block = Ba::Block;
+	type State = Ba::State;
+	type Auxiliary = Ba::Auxiliary;
+	type Error = Ba::Error;
+}
+
+impl<Ba: ChainQuery> ChainQuery for OverlayBackend<Ba> where
+	Ba::State: Clone,
+{
+	fn genesis(&self) -> <Self::Block as Block>::Identifier {
+		self.inner.genesis()
+	}
+	fn head(&self) -> <Self::Block as Block>::Identifier {
+		self.head.clone().unwrap_or_else(|| self.inner.head())
+	}
+	fn contains(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<bool, Self::Error> {
+		Ok(self.blocks_and_states.contains_key(hash) || self.inner.contains(hash)?)
+	}
+	fn is_canon(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<bool, Self::Error> {
+		if let Some(data) = self.blocks_and_states.get(hash) {
+			return Ok(data.is_canon)
+		}
+
+		match self.canon.get(hash) {
+			Some(is_canon) => Ok(*is_canon),
+			None => self.inner.is_canon(hash),
+		}
+	}
+	fn lookup_canon_depth(
+		&self,
+		depth: usize,
+	) -> Result<Option<<Self::Block as Block>::Identifier>, Self::Error> {
+		match self.canon_depth_mappings.get(&depth) {
+			Some(id) => Ok(id.clone()),
+			None => self.inner.lookup_canon_depth(depth),
+		}
+	}
+	fn auxiliary(
+		&self,
+		key: &<Self::Auxiliary as Auxiliary<Self::Block>>::Key,
+	) -> Result<Option<Self::Auxiliary>, Self::Error> {
+		match self.auxiliaries.get(key) {
+			Some(value) => Ok(value.clone()),
+			None => self.inner.auxiliary(key),
+		}
+	}
+	fn depth_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<usize, Self::Error> {
+		match self.blocks_and_states.get(hash) {
+			Some(data) => Ok(data.depth),
+			None => self.inner.depth_at(hash),
+		}
+	}
+	fn children_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<Vec<<Self::Block as Block>::Identifier>, Self::Error> {
+		let mut children = match self.blocks_and_states.get(hash) {
+			Some(data) => data.children.clone(),
+			None => self.inner.children_at(hash)?,
+		};
+		if let Some(extra) = self.children.get(hash) {
+			children.extend(extra.iter().cloned());
+		}
+		Ok(children)
+	}
+	fn state_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<Self::State, Self::Error> {
+		match self.blocks_and_states.get(hash) {
+			Some(data) => Ok(data.state.clone()),
+			None => self.inner.state_at(hash),
+		}
+	}
+	fn block_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<Self::Block, Self::Error> {
+		match self.blocks_and_states.get(hash) {
+			Some(data) => Ok(data.block.clone()),
+			None => self.inner.block_at(hash),
+		}
+	}
+}
+
+impl<Ba: ChainQuery> ChainSettlement for OverlayBackend<Ba> where
+	Ba::State: Clone,
+{
+	fn insert_block(
+		&mut self,
+		id: <Self::Block as Block>::Identifier,
+		block: Self::Block,
+		state: Self::State,
+		depth: usize,
+		children: Vec<<Self::Block as Block>::Identifier>,
+		is_canon: bool
+	) {
+		self.blocks_and_states.insert(id, BlockData {
+			block, state, depth, children, is_canon
+		});
+	}
+	fn push_child(
+		&mut self,
+		id: <Self::Block as Block>::Identifier,
+		child: <Self::Block as Block>::Identifier,
+	) {
+		match self.blocks_and_states.get_mut(&id) {
+			Some(data) => data.children.push(child),
+			None => self.children.entry(id).or_default().push(child),
+		}
+	}
+	fn set_canon(
+		&mut self,
+		id: <Self::Block as Block>::Identifier,
+		is_canon: bool
+	) {
+		match self.blocks_and_states.get_mut(&id) {
+			Some(data) => data.is_canon = is_canon,
+			None => { self.canon.insert(id, is_canon); },
+		}
+	}
+	fn insert_canon_depth_mapping(
+		&mut self,
+		depth: usize,
+		id: <Self::Block as Block>::Identifier,
+	) {
+		self.canon_depth_mappings.insert(depth, Some(id));
+	}
+	fn remove_canon_depth_mapping(
+		&mut self,
+		depth: &usize
+	) {
+		self.canon_depth_mappings.insert(*depth, None);
+	}
+	fn insert_auxiliary(
+		&mut self,
+		key: <Self::Auxiliary as Auxiliary<Self::Block>>::Key,
+		value: Self::Auxiliary
+	) {
+		self.auxiliaries.insert(key, Some(value));
+	}
+	fn remove_auxiliary(
+		&mut self,
+		key: &<Self::Auxiliary as Auxiliary<Self::Block>>::Key,
+	) {
+		self.auxiliaries.insert(key.clone(), None);
+	}
+	fn set_head(
+		&mut self,
+		head: <Self::Block as Block>::Identifier
+	) {
+		self.head = Some(head);
+	}
+}
+
+impl<Ba: ChainQuery> Committable for OverlayBackend<Ba> where
+	Ba::State: Clone,
+	Ba::Error: OperationError,
+{
+	type Operation = Operation<Self::Block, Self::State, Self::Auxiliary>;
+
+	fn commit(
+		&mut self,
+		operation: Operation<Self::Block, Self::State, Self::Auxiliary>,
+	) -> Result<(), Self::Error> {
+		operation.settle(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::backend::SharedCommittable;
+	use crate::test_support::{TestBlock, TestBackend, genesis_backend, test_generator};
+
+	/// Inner backend with a canonical chain of three blocks, and an overlay
+	/// forking it from the first one with blocks 101, 102 and 103.
+	fn forked_overlay() -> (Vec<u64>, OverlayBackend<TestBackend>) {
+		let inner = genesis_backend();
+		test_generator(&inner, 0).linear("a", 3).unwrap();
+		let canon = (0..=3).map(|depth| inner.lookup_canon_depth(depth).unwrap().unwrap())
+			.collect::<Vec<_>>();
+
+		let mut overlay = OverlayBackend::new(inner);
+		overlay.commit(Operation {
+			import_block: vec![
+				ImportOperation { block: TestBlock::new(101, canon[1]), state: () },
+				ImportOperation { block: TestBlock::new(102, 101), state: () },
+				ImportOperation { block: TestBlock::new(103, 102), state: () },
+			],
+			set_head: Some(103),
+			..Default::default()
+		}).unwrap();
+
+		(canon, overlay)
+	}
+
+	#[test]
+	fn fork_retracts_inner_canonical_blocks() {
+		let (canon, overlay) = forked_overlay();
+
+		assert_eq!(overlay.head(), 103);
+		assert_eq!(overlay.inner().head(), canon[3]);
+		assert!(overlay.is_canon(&canon[1]).unwrap());
+		assert!(!overlay.is_canon(&canon[2]).unwrap());
+		assert!(!overlay.is_canon(&canon[3]).unwrap());
+		assert!(overlay.inner().is_canon(&canon[2]).unwrap());
+		assert_eq!(overlay.lookup_canon_depth(2).unwrap(), Some(101));
+		assert_eq!(overlay.lookup_canon_depth(4).unwrap(), Some(103));
+		assert_eq!(overlay.inner().lookup_canon_depth(2).unwrap(), Some(canon[2]));
+		assert_eq!(overlay.children_at(&canon[1]).unwrap(), vec![canon[2], 101]);
+		assert_eq!(overlay.inner().children_at(&canon[1]).unwrap(), vec![canon[2]]);
+	}
+
+	#[test]
+	fn discard_restores_inner_view() {
+		let (canon, mut overlay) = forked_overlay();
+		overlay.discard();
+
+		assert_eq!(overlay.head(), canon[3]);
+		assert!(!overlay.contains(&101).unwrap());
+		assert!(overlay.is_canon(&canon[2]).unwrap());
+		assert_eq!(overlay.lookup_canon_depth(2).unwrap(), Some(canon[2]));
+		assert_eq!(overlay.lookup_canon_depth(4).unwrap(), None);
+		assert_eq!(overlay.children_at(&canon[1]).unwrap(), vec![canon[2]]);
+	}
+
+	#[test]
+	fn operation_applies_overlay_to_inner() {
+		let (canon, overlay) = forked_overlay();
+		overlay.inner().commit(overlay.to_operation()).unwrap();
+
+		let inner = overlay.inner();
+		assert_eq!(inner.head(), overlay.head());
+		assert!(!inner.is_canon(&canon[2]).unwrap());
+		for depth in 0..=4 {
+			assert_eq!(inner.lookup_canon_depth(depth).unwrap(), overlay.lookup_canon_depth(depth).unwrap());
+		}
+	}
+
+	#[test]
+	fn operation_for_block_applies_only_its_route() {
+		let (canon, overlay) = forked_overlay();
+		overlay.inner().commit(overlay.to_operation_for(&102)).unwrap();
+
+		let inner = overlay.inner();
+		assert_eq!(inner.head(), 102);
+		assert!(inner.contains(&101).unwrap());
+		assert!(!inner.contains(&103).unwrap());
+		assert!(!inner.is_canon(&canon[2]).unwrap());
+		assert_eq!(inner.lookup_canon_depth(3).unwrap(), Some(102));
+		assert_eq!(inner.lookup_canon_depth(4).unwrap(), None);
+	}
+}
@@ -1,22 +1,76 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::error as stderror;
 use core::convert::Infallible;
-use crate::StorageExternalities;
+use core::ops::Bound;
+use crate::{StorageExternalities, AsExternalities};
 
-/// State stored in memory.
+/// A changed storage entry, as key, old value and new value.
+pub type StorageChange = (Vec<u8>, Option<Vec<u8>>, Option<Vec<u8>>);
+
+/// State stored in memory, ordered by key so that removing a prefix only
+/// visits the keys under it.
 #[derive(Clone, Default)]
 pub struct KeyValueMemoryState {
-	storage: HashMap<Vec<u8>, Vec<u8>>,
+	storage: BTreeMap<Vec<u8>, Vec<u8>>,
 }
 
-impl AsRef<HashMap<Vec<u8>, Vec<u8>>> for KeyValueMemoryState {
-	fn as_ref(&self) -> &HashMap<Vec<u8>, Vec<u8>> {
+impl KeyValueMemoryState {
+	/// Get up to `limit` entries in key order, starting after the given
+	/// key. Callers paginate by passing the last key of the previous page.
+	pub fn page(&self, start_after: Option<&[u8]>, limit: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+		let start = match start_after {
+			Some(start) => Bound::Excluded(start),
+			None => Bound::Unbounded,
+		};
+
+		self.storage.range::<[u8], _>((start, Bound::Unbounded))
+			.take(limit)
+			.map(|(key, value)| (key.clone(), value.clone()))
+			.collect()
+	}
+
+	fn remove_prefix(&mut self, prefix: &[u8]) {
+		let keys = self.storage.range::<[u8], _>((Bound::Included(prefix), Bound::Unbounded))
+			.map(|(key, _)| key)
+			.take_while(|key| key.starts_with(prefix))
+			.cloned()
+			.collect::<Vec<_>>();
+
+		for key in keys {
+			self.storage.remove(&key);
+		}
+	}
+
+	/// Get changed keys between this state and a newer one, as tuples of
+	/// key, old value and new value, sorted by key.
+	pub fn diff(&self, other: &Self) -> Vec<StorageChange> {
+		let mut changes = Vec::new();
+
+		for (key, old) in &self.storage {
+			match other.storage.get(key) {
+				Some(new) if new == old => (),
+				new => changes.push((key.clone(), Some(old.clone()), new.cloned())),
+			}
+		}
+		for (key, new) in &other.storage {
+			if !self.storage.contains_key(key) {
+				changes.push((key.clone(), None, Some(new.clone())));
+			}
+		}
+
+		changes.sort_by(|a, b| a.0.cmp(&b.0));
+		changes
+	}
+}
+
+impl AsRef<BTreeMap<Vec<u8>, Vec<u8>>> for KeyValueMemoryState {
+	fn as_ref(&self) -> &BTreeMap<Vec<u8>, Vec<u8>> {
 		&self.storage
 	}
 }
 
-impl AsMut<HashMap<Vec<u8>, Vec<u8>>> for KeyValueMemoryState {
-	fn as_mut(&mut self) -> &mut HashMap<Vec<u8>, Vec<u8>> {
+impl AsMut<BTreeMap<Vec<u8>, Vec<u8>>> for KeyValueMemoryState {
+	fn as_mut(&mut self) -> &mut BTreeMap<Vec<u8>, Vec<u8>> {
 		&mut self.storage
 	}
 }
@@ -33,6 +87,10 @@ impl StorageExternalities<Infallible> for KeyValueMemoryState {
 	fn remove_storage(&mut self, key: &[u8]) {
 		self.storage.remove(key);
 	}
+
+	fn remove_prefix(&mut self, prefix: &[u8]) {
+		KeyValueMemoryState::remove_prefix(self, prefix)
+	}
 }
 
 impl StorageExternalities<Box<dyn stderror::Error>> for KeyValueMemoryState {
@@ -47,4 +105,96 @@ impl StorageExternalities<Box<dyn stderror::Error>> for KeyValueMemoryState {
 	fn remove_storage(&mut self, key: &[u8]) {
 		self.storage.remove(key);
 	}
+
+	fn remove_prefix(&mut self, prefix: &[u8]) {
+		KeyValueMemoryState::remove_prefix(self, prefix)
+	}
+}
+
+impl AsExternalities<dyn StorageExternalities<Infallible>> for KeyValueMemoryState {
+	fn as_externalities(&mut self) -> &mut (dyn StorageExternalities<Infallible> + 'static) {
+		self
+	}
+}
+
+impl AsExternalities<dyn StorageExternalities<Box<dyn stderror::Error>>> for KeyValueMemoryState {
+	fn as_externalities(&mut self) -> &mut (dyn StorageExternalities<Box<dyn stderror::Error>> + 'static) {
+		self
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn kill_namespace_leaves_other_keys() {
+		let mut state = KeyValueMemoryState::default();
+		{
+			let storage: &mut dyn StorageExternalities<Infallible> = state.as_externalities();
+			storage.namespace(b"a").write_storage(b"key".to_vec(), b"a".to_vec());
+			storage.namespace(b"ab").write_storage(b"key".to_vec(), b"ab".to_vec());
+			storage.namespace(b"b").write_storage(b"key".to_vec(), b"b".to_vec());
+			storage.write_storage(b"a".to_vec(), b"raw".to_vec());
+			storage.kill_namespace(b"a");
+
+			assert_eq!(storage.namespace(b"a").read_storage(b"key").unwrap(), None);
+			assert_eq!(storage.namespace(b"ab").read_storage(b"key").unwrap(), Some(b"ab".to_vec()));
+			assert_eq!(storage.namespace(b"b").read_storage(b"key").unwrap(), Some(b"b".to_vec()));
+			assert_eq!(storage.read_storage(b"a").unwrap(), Some(b"raw".to_vec()));
+			storage.kill_namespace(b"b");
+		}
+
+		assert_eq!(state.as_ref().len(), 2);
+	}
+
+	#[test]
+	fn raw_keys_do_not_collide_with_namespaces() {
+		let mut state = KeyValueMemoryState::default();
+		let storage: &mut dyn StorageExternalities<Infallible> = state.as_externalities();
+		let mut raw = 7u32.to_le_bytes().to_vec();
+		raw.extend_from_slice(b"counter");
+		storage.write_storage(raw.clone(), b"raw".to_vec());
+		storage.namespace(b"counter").write_storage(Vec::new(), b"namespaced".to_vec());
+		storage.kill_namespace(b"counter");
+
+		assert_eq!(storage.read_storage(&raw).unwrap(), Some(b"raw".to_vec()));
+	}
+
+	fn state(entries: &[(&[u8], &[u8])]) -> KeyValueMemoryState {
+		let mut state = KeyValueMemoryState::default();
+		for (key, value) in entries {
+			state.as_mut().insert(key.to_vec(), value.to_vec());
+		}
+		state
+	}
+
+	#[test]
+	fn diff_reports_changed_keys_sorted() {
+		let old = state(&[(b"changed", b"1"), (b"removed", b"2"), (b"same", b"3")]);
+		let new = state(&[(b"changed", b"4"), (b"added", b"5"), (b"same", b"3")]);
+
+		assert_eq!(old.diff(&new), vec![
+			(b"added".to_vec(), None, Some(b"5".to_vec())),
+			(b"changed".to_vec(), Some(b"1".to_vec()), Some(b"4".to_vec())),
+			(b"removed".to_vec(), Some(b"2".to_vec()), None),
+		]);
+		assert!(new.diff(&new).is_empty());
+	}
+
+	#[test]
+	fn page_walks_keys_in_order() {
+		let state = state(&[(b"c", b"3"), (b"a", b"1"), (b"e", b"5"), (b"b", b"2"), (b"d", b"4")]);
+
+		let first = state.page(None, 2);
+		assert_eq!(first, vec![(b"a".to_vec(), b"1".to_vec()), (b"b".to_vec(), b"2".to_vec())]);
+
+		let second = state.page(Some(&first[1].0), 2);
+		assert_eq!(second, vec![(b"c".to_vec(), b"3".to_vec()), (b"d".to_vec(), b"4".to_vec())]);
+
+		let last = state.page(Some(&second[1].0), 2);
+		assert_eq!(last, vec![(b"e".to_vec(), b"5".to_vec())]);
+		assert!(state.page(Some(b"e"), 2).is_empty());
+		assert_eq!(state.page(Some(b"bb"), 1), vec![(b"c".to_vec(), b"3".to_vec())]);
+	}
 }
@@ -46,6 +46,54 @@ pub trait ChainQuery: Store {
 		depth: usize,
 	) -> Result<Option<<Self::Block as Block>::Identifier>, Self::Error>;
 
+	/// Check whether canonical blocks exist at all depths from `from` to
+	/// `to`, inclusive.
+	fn depth_range_exists(
+		&self,
+		from: usize,
+		to: usize,
+	) -> Result<bool, Self::Error> {
+		for depth in from..=to {
+			if self.lookup_canon_depth(depth)?.is_none() {
+				return Ok(false)
+			}
+		}
+
+		Ok(true)
+	}
+
+	/// Call `f` with each canonical block from depth `from` to `to`,
+	/// inclusive, reading one block at a time. Stops early at the first
+	/// missing depth, and returns the number of blocks visited, so that a
+	/// walk past the head can be told apart from a full one. Shared backends
+	/// should override this to take the range from a single view of the
+	/// chain, so that a concurrent reorg cannot mix blocks of two chains.
+	/// `f` may fail with its own error type, such as an I/O error of an
+	/// exporter.
+	fn for_each_block<F, E>(
+		&self,
+		from: usize,
+		to: usize,
+		mut f: F,
+	) -> Result<usize, E> where
+		Self: Sized,
+		F: FnMut(<Self::Block as Block>::Identifier, Self::Block) -> Result<(), E>,
+		E: From<Self::Error>,
+	{
+		let mut visited = 0;
+		for depth in from..=to {
+			let hash = match self.lookup_canon_depth(depth)? {
+				Some(hash) => hash,
+				None => break,
+			};
+			let block = self.block_at(&hash)?;
+			f(hash, block)?;
+			visited += 1;
+		}
+
+		Ok(visited)
+	}
+
 	/// Get the auxiliary value by key.
 	fn auxiliary(
 		&self,
@@ -77,6 +125,90 @@ pub trait ChainQuery: Store {
 	) -> Result<Self::Block, Self::Error>;
 }
 
+impl<T: Store> Store for &T {
+	type Block = T::Block;
+	type State = T::State;
+	type Auxiliary = T::Auxiliary;
+	type Error = T::Error;
+}
+
+/// Query a borrowed backend, such as one behind a lock guard, so that an
+/// `OverlayBackend` can be layered on top of it without taking ownership.
+impl<T: ChainQuery> ChainQuery for &T {
+	fn genesis(&self) -> <Self::Block as Block>::Identifier {
+		(**self).genesis()
+	}
+	fn head(&self) -> <Self::Block as Block>::Identifier {
+		(**self).head()
+	}
+	fn contains(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<bool, Self::Error> {
+		(**self).contains(hash)
+	}
+	fn is_canon(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<bool, Self::Error> {
+		(**self).is_canon(hash)
+	}
+	fn lookup_canon_depth(
+		&self,
+		depth: usize,
+	) -> Result<Option<<Self::Block as Block>::Identifier>, Self::Error> {
+		(**self).lookup_canon_depth(depth)
+	}
+	fn depth_range_exists(
+		&self,
+		from: usize,
+		to: usize,
+	) -> Result<bool, Self::Error> {
+		(**self).depth_range_exists(from, to)
+	}
+	fn for_each_block<F, E>(
+		&self,
+		from: usize,
+		to: usize,
+		f: F,
+	) -> Result<usize, E> where
+		F: FnMut(<Self::Block as Block>::Identifier, Self::Block) -> Result<(), E>,
+		E: From<Self::Error>,
+	{
+		(**self).for_each_block(from, to, f)
+	}
+	fn auxiliary(
+		&self,
+		key: &<Self::Auxiliary as Auxiliary<Self::Block>>::Key,
+	) -> Result<Option<Self::Auxiliary>, Self::Error> {
+		(**self).auxiliary(key)
+	}
+	fn depth_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<usize, Self::Error> {
+		(**self).depth_at(hash)
+	}
+	fn children_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<Vec<<Self::Block as Block>::Identifier>, Self::Error> {
+		(**self).children_at(hash)
+	}
+	fn state_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<Self::State, Self::Error> {
+		(**self).state_at(hash)
+	}
+	fn block_at(
+		&self,
+		hash: &<Self::Block as Block>::Identifier,
+	) -> Result<Self::Block, Self::Error> {
+		(**self).block_at(hash)
+	}
+}
+
 /// Database settlement for chain backend.
 pub trait ChainSettlement: Store {
 	/// Insert a new block into the database.
@@ -1,5 +1,7 @@
+use std::fmt;
 use std::sync::MutexGuard;
-use crate::backend::{SharedCommittable, Store, Operation, ImportOperation};
+use log::warn;
+use crate::backend::{SharedCommittable, Store, ChainQuery, Operation, ImportOperation, tree_route};
 use crate::{Block, Auxiliary};
 
 /// Block importer.
@@ -49,6 +51,54 @@ impl<'a, Ba: Store> ImportAction<'a, Ba> where
 		self.pending.set_head = Some(head);
 	}
 
+	/// Set head to given hash, unless that would retract more than
+	/// `max_reorg_depth` canonical blocks. Returns whether the head is set.
+	///
+	/// A refused block is still imported, and can later be made head
+	/// deliberately through `set_head`.
+	pub fn set_head_within_reorg_depth(
+		&mut self,
+		head: <Ba::Block as Block>::Identifier,
+		max_reorg_depth: usize,
+	) -> Result<bool, Ba::Error> where
+		Ba: ChainQuery,
+		<Ba::Block as Block>::Identifier: fmt::Debug,
+	{
+		let retracted = self.retracted_by(&head)?;
+
+		if retracted > max_reorg_depth {
+			warn!(
+				"Refusing reorg to {:?}: it retracts {} canonical blocks, more than the limit of {}",
+				head, retracted, max_reorg_depth,
+			);
+			return Ok(false)
+		}
+
+		self.set_head(head);
+		Ok(true)
+	}
+
+	/// Number of canonical blocks retracted if the given block becomes head.
+	fn retracted_by(
+		&self,
+		head: &<Ba::Block as Block>::Identifier,
+	) -> Result<usize, Ba::Error> where
+		Ba: ChainQuery,
+	{
+		let mut id = head.clone();
+
+		// Walk back pending blocks until one known to the backend is found.
+		while let Some(op) = self.pending.import_block.iter().find(|op| op.block.id() == id) {
+			match op.block.parent_id() {
+				Some(parent_id) => id = parent_id,
+				None => return Ok(0),
+			}
+		}
+
+		let route = tree_route(self.backend, &self.backend.head(), &id)?;
+		Ok(route.retracted().len())
+	}
+
 	/// Insert auxiliary value.
 	pub fn insert_auxiliary(&mut self, aux: Ba::Auxiliary) {
 		self.pending.insert_auxiliaries.push(aux);
@@ -64,3 +114,49 @@ impl<'a, Ba: Store> ImportAction<'a, Ba> where
 		Ok(self.backend.commit(self.into())?)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::backend::ImportLock;
+	use crate::test_support::{TestBlock, genesis_backend, test_generator};
+
+	#[test]
+	fn reorg_within_limit_sets_pending_head() {
+		let backend = genesis_backend();
+		let head = test_generator(&backend, 0).linear("a", 3).unwrap();
+		let fork_point = backend.lookup_canon_depth(2).unwrap().unwrap();
+		let lock = ImportLock::new();
+
+		let mut action = ImportAction::new(&backend, lock.lock());
+		action.import_block(TestBlock::new(101, fork_point), ());
+		action.import_block(TestBlock::new(102, 101), ());
+		assert_eq!(action.retracted_by(&102).unwrap(), 1);
+		assert_eq!(action.retracted_by(&head).unwrap(), 0);
+		assert!(action.set_head_within_reorg_depth(102, 1).unwrap());
+		action.commit().unwrap();
+
+		assert_eq!(backend.head(), 102);
+	}
+
+	#[test]
+	fn reorg_over_limit_imports_without_setting_head() {
+		let backend = genesis_backend();
+		let mut generator = test_generator(&backend, 0);
+		let head = generator.linear("a", 3).unwrap();
+		let known_fork = generator.fork_at("b", 1, 1).unwrap();
+		let lock = ImportLock::new();
+
+		let mut action = ImportAction::new(&backend, lock.lock());
+		action.import_block(TestBlock::new(201, 0), ());
+		action.import_block(TestBlock::new(202, 201), ());
+		assert_eq!(action.retracted_by(&known_fork).unwrap(), 2);
+		assert_eq!(action.retracted_by(&202).unwrap(), 3);
+		assert!(!action.set_head_within_reorg_depth(known_fork, 1).unwrap());
+		assert!(!action.set_head_within_reorg_depth(202, 2).unwrap());
+		action.commit().unwrap();
+
+		assert_eq!(backend.head(), head);
+		assert!(backend.contains(&202).unwrap());
+	}
+}
@@ -0,0 +1,166 @@
+use std::{fmt, error as stderror};
+use std::time::Duration;
+
+/// Minimum time to wait before checking a skipped slot again.
+pub const MIN_IDLE_WAIT: Duration = Duration::from_millis(100);
+
+/// Chain spec parsing errors.
+#[derive(Debug)]
+pub enum SpecError {
+	/// Line is not of the form `key = value`.
+	InvalidLine(usize),
+	/// Value of a known key cannot be parsed.
+	InvalidValue(usize),
+}
+
+impl fmt::Display for SpecError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl stderror::Error for SpecError { }
+
+/// Block authoring configuration.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AuthorConfig {
+	/// Time between two authoring slots.
+	pub interval: Duration,
+	/// Skip slots that would produce a block without extrinsics.
+	pub no_empty_blocks: bool,
+	/// Maximum time without a block, even if empty blocks are skipped.
+	pub keep_alive: Duration,
+}
+
+impl Default for AuthorConfig {
+	fn default() -> Self {
+		Self {
+			interval: Duration::from_millis(0),
+			no_empty_blocks: false,
+			keep_alive: Duration::from_secs(60),
+		}
+	}
+}
+
+impl AuthorConfig {
+	/// Read the configuration from a chain spec of `key = value` lines.
+	/// Known keys are `block_interval_ms`, `no_empty_blocks` and
+	/// `keep_alive_ms`; missing ones keep their default. Other keys belong
+	/// to other parts of the spec and are ignored, as are blank lines and
+	/// lines starting with `#`. Line numbers in errors start at 1.
+	pub fn from_chain_spec(spec: &str) -> Result<Self, SpecError> {
+		let mut config = Self::default();
+
+		for (index, line) in spec.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue
+			}
+
+			let number = index + 1;
+			let mut parts = line.splitn(2, '=');
+			let key = parts.next().map(str::trim).unwrap_or_default();
+			let value = parts.next().map(str::trim).ok_or(SpecError::InvalidLine(number))?;
+			let millis = || value.parse().map(Duration::from_millis)
+				.map_err(|_| SpecError::InvalidValue(number));
+
+			match key {
+				"block_interval_ms" => config.interval = millis()?,
+				"keep_alive_ms" => config.keep_alive = millis()?,
+				"no_empty_blocks" => config.no_empty_blocks = value.parse()
+					.map_err(|_| SpecError::InvalidValue(number))?,
+				_ => (),
+			}
+		}
+
+		Ok(config)
+	}
+
+	/// Decide whether a block should be authored in the current slot.
+	pub fn should_author(&self, since_last_block: Duration, pending_extrinsics: usize) -> bool {
+		if !self.no_empty_blocks || pending_extrinsics > 0 {
+			return true
+		}
+
+		since_last_block >= self.keep_alive
+	}
+
+	/// Time to wait after a skipped slot. Never shorter than
+	/// `MIN_IDLE_WAIT`, so that a zero interval does not busy-loop, and
+	/// never past the keep-alive deadline.
+	pub fn idle_wait(&self, since_last_block: Duration) -> Duration {
+		let until_keep_alive = self.keep_alive.checked_sub(since_last_block)
+			.unwrap_or_default();
+
+		self.interval.max(MIN_IDLE_WAIT).min(until_keep_alive.max(MIN_IDLE_WAIT))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn config(no_empty_blocks: bool) -> AuthorConfig {
+		AuthorConfig { no_empty_blocks, ..Default::default() }
+	}
+
+	#[test]
+	fn authors_every_slot_by_default() {
+		assert!(config(false).should_author(Duration::from_secs(0), 0));
+	}
+
+	#[test]
+	fn skips_empty_slots_until_keep_alive() {
+		let config = config(true);
+		assert!(!config.should_author(Duration::from_secs(0), 0));
+		assert!(!config.should_author(Duration::from_secs(59), 0));
+		assert!(config.should_author(Duration::from_secs(60), 0));
+	}
+
+	#[test]
+	fn authors_when_extrinsics_are_pending() {
+		assert!(config(true).should_author(Duration::from_secs(0), 1));
+	}
+
+	#[test]
+	fn idle_wait_does_not_busy_loop() {
+		let config = config(true);
+		assert_eq!(config.idle_wait(Duration::from_secs(0)), MIN_IDLE_WAIT);
+		assert_eq!(config.idle_wait(Duration::from_secs(120)), MIN_IDLE_WAIT);
+	}
+
+	#[test]
+	fn idle_wait_stops_at_keep_alive() {
+		let config = AuthorConfig { interval: Duration::from_secs(10), ..config(true) };
+		assert_eq!(config.idle_wait(Duration::from_secs(0)), Duration::from_secs(10));
+		assert_eq!(config.idle_wait(Duration::from_secs(55)), Duration::from_secs(5));
+	}
+
+	#[test]
+	fn reads_chain_spec() {
+		let config = AuthorConfig::from_chain_spec("\
+			# Counter chain\n\
+			name = counter\n\
+			\n\
+			block_interval_ms = 5000\n\
+			no_empty_blocks = true\n").unwrap();
+
+		assert_eq!(config, AuthorConfig {
+			interval: Duration::from_secs(5),
+			no_empty_blocks: true,
+			..Default::default()
+		});
+	}
+
+	#[test]
+	fn rejects_invalid_chain_spec() {
+		assert!(matches!(
+			AuthorConfig::from_chain_spec("block_interval_ms = 10\nkeep_alive_ms"),
+			Err(SpecError::InvalidLine(2)),
+		));
+		assert!(matches!(
+			AuthorConfig::from_chain_spec("no_empty_blocks = yes"),
+			Err(SpecError::InvalidValue(1)),
+		));
+	}
+}
@@ -0,0 +1,203 @@
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::backend::{Store, ChainQuery};
+use crate::import::Error;
+use crate::{Block, ExtrinsicBuilder, AsExternalities};
+
+type Snapshot<E, Ba> = (
+	<E as ExtrinsicBuilder>::BuildBlock,
+	<Ba as Store>::State,
+	<E as ExtrinsicBuilder>::Extrinsic,
+);
+
+/// Block builder.
+pub struct BlockBuilder<'a, E: ExtrinsicBuilder, Ba: ChainQuery> {
+	executor: &'a E,
+	pending_block: E::BuildBlock,
+	pending_state: Ba::State,
+	undo_stack: Vec<Snapshot<E, Ba>>,
+	seal_delay: Option<Duration>,
+	deadline: Option<Instant>,
+}
+
+impl<'a, E, Ba> BlockBuilder<'a, E, Ba> where
+	E: ExtrinsicBuilder<Block=Ba::Block>,
+	Ba: ChainQuery,
+	Ba::State: AsExternalities<E::Externalities>,
+{
+	/// Create a new block builder on top of the given parent.
+	pub fn new(
+		backend: &Ba,
+		executor: &'a E,
+		parent_hash: &<Ba::Block as Block>::Identifier,
+		inherent: E::Inherent,
+	) -> Result<Self, Error> {
+		let parent_block = backend.block_at(parent_hash)
+			.map_err(|e| Error::Backend(Box::new(e)))?;
+		let mut pending_state = backend.state_at(parent_hash)
+			.map_err(|e| Error::Backend(Box::new(e)))?;
+
+		let pending_block = executor.initialize_block(
+			&parent_block,
+			pending_state.as_externalities(),
+			inherent,
+		).map_err(|e| Error::Executor(Box::new(e)))?;
+
+		Ok(Self {
+			executor,
+			pending_block,
+			pending_state,
+			undo_stack: Vec::new(),
+			seal_delay: None,
+			deadline: None,
+		})
+	}
+
+	/// Create a new block builder on top of the current head.
+	pub fn for_current_tip(
+		backend: &Ba,
+		executor: &'a E,
+		inherent: E::Inherent,
+	) -> Result<Self, Error> {
+		Self::new(backend, executor, &backend.head(), inherent)
+	}
+
+	/// Delay the return of `finalize`, to simulate block production time.
+	pub fn with_seal_delay(mut self, delay: Duration) -> Self {
+		self.seal_delay = Some(delay);
+		self
+	}
+
+	/// Reject extrinsics and finalization once the deadline has passed.
+	pub fn with_deadline(mut self, deadline: Instant) -> Self {
+		self.deadline = Some(deadline);
+		self
+	}
+
+	/// Apply a new extrinsic. Extrinsics applied before can no longer be
+	/// undone.
+	pub fn apply_extrinsic(&mut self, extrinsic: E::Extrinsic) -> Result<(), Error> {
+		self.undo_stack.clear();
+		self.execute_extrinsic(extrinsic)
+	}
+
+	/// Finalize the block, returning the built block and its state.
+	pub fn finalize(mut self) -> Result<(E::BuildBlock, Ba::State), Error> {
+		self.check_deadline()?;
+
+		self.executor.finalize_block(
+			&mut self.pending_block,
+			self.pending_state.as_externalities(),
+		).map_err(|e| Error::Executor(Box::new(e)))?;
+
+		if let Some(delay) = self.seal_delay {
+			thread::sleep(delay);
+		}
+
+		Ok((self.pending_block, self.pending_state))
+	}
+
+	fn execute_extrinsic(&mut self, extrinsic: E::Extrinsic) -> Result<(), Error> {
+		self.check_deadline()?;
+
+		self.executor.apply_extrinsic(
+			&mut self.pending_block,
+			extrinsic,
+			self.pending_state.as_externalities(),
+		).map_err(|e| Error::Executor(Box::new(e)))
+	}
+
+	fn check_deadline(&self) -> Result<(), Error> {
+		match self.deadline {
+			Some(deadline) if Instant::now() >= deadline => Err(Error::DeadlineExceeded),
+			_ => Ok(()),
+		}
+	}
+}
+
+impl<'a, E, Ba> BlockBuilder<'a, E, Ba> where
+	E: ExtrinsicBuilder<Block=Ba::Block>,
+	E::BuildBlock: Clone,
+	E::Extrinsic: Clone,
+	Ba: ChainQuery,
+	Ba::State: AsExternalities<E::Externalities> + Clone,
+{
+	/// Apply a new extrinsic, keeping a snapshot of the pending block and
+	/// state so that it can be undone. If applying fails, the snapshot is
+	/// restored.
+	pub fn apply_extrinsic_undoable(&mut self, extrinsic: E::Extrinsic) -> Result<(), Error> {
+		let snapshot = (
+			self.pending_block.clone(),
+			self.pending_state.clone(),
+			extrinsic.clone(),
+		);
+
+		match self.execute_extrinsic(extrinsic) {
+			Ok(()) => {
+				self.undo_stack.push(snapshot);
+				Ok(())
+			},
+			Err(e) => {
+				self.pending_block = snapshot.0;
+				self.pending_state = snapshot.1;
+				Err(e)
+			},
+		}
+	}
+
+	/// Undo the last extrinsic applied with `apply_extrinsic_undoable`,
+	/// returning it.
+	pub fn undo_last_extrinsic(&mut self) -> Result<E::Extrinsic, Error> {
+		let (block, state, extrinsic) = self.undo_stack.pop()
+			.ok_or(Error::NothingToUndo)?;
+		self.pending_block = block;
+		self.pending_state = state;
+
+		Ok(extrinsic)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_support::{TestExecutor, genesis_backend};
+
+	#[test]
+	fn undo_restores_previous_block() {
+		let backend = genesis_backend();
+		let mut builder = BlockBuilder::for_current_tip(&backend, &TestExecutor, ()).unwrap();
+
+		builder.apply_extrinsic_undoable(1).unwrap();
+		builder.apply_extrinsic_undoable(2).unwrap();
+		assert_eq!(builder.undo_last_extrinsic().unwrap(), 2);
+
+		let ((_, extrinsics), ()) = builder.finalize().unwrap();
+		assert_eq!(extrinsics, vec![1]);
+		assert!(matches!(
+			BlockBuilder::for_current_tip(&backend, &TestExecutor, ()).unwrap().undo_last_extrinsic(),
+			Err(Error::NothingToUndo)
+		));
+	}
+
+	#[test]
+	fn passed_deadline_rejects_extrinsics_and_finalize() {
+		let backend = genesis_backend();
+		let mut builder = BlockBuilder::for_current_tip(&backend, &TestExecutor, ()).unwrap()
+			.with_deadline(Instant::now());
+
+		assert!(matches!(builder.apply_extrinsic(1), Err(Error::DeadlineExceeded)));
+		assert!(matches!(builder.apply_extrinsic_undoable(1), Err(Error::DeadlineExceeded)));
+		assert!(matches!(builder.finalize(), Err(Error::DeadlineExceeded)));
+	}
+
+	#[test]
+	fn seal_delay_delays_finalize() {
+		let backend = genesis_backend();
+		let builder = BlockBuilder::for_current_tip(&backend, &TestExecutor, ()).unwrap()
+			.with_seal_delay(Duration::from_millis(20));
+
+		let start = Instant::now();
+		builder.finalize().unwrap();
+		assert!(start.elapsed() >= Duration::from_millis(20));
+	}
+}
@@ -1,12 +1,46 @@
 //! Chain importer and block builder.
 
 mod action;
+mod author;
+mod builder;
 mod traits;
 
 pub use self::action::ImportAction;
-pub use self::traits::{RawImporter, SharedRawImporter, BlockImporter, SharedBlockImporter};
+pub use self::author::{AuthorConfig, SpecError, MIN_IDLE_WAIT};
+pub use self::builder::BlockBuilder;
+pub use self::traits::{
+	RawImporter, SharedRawImporter, BlockImporter, SharedBlockImporter,
+	BlockOrigin, ImportQueue,
+};
 
-use std::sync::{Arc, Mutex};
+use std::{fmt, mem, error as stderror};
+use std::num::NonZeroUsize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock, Condvar};
+use log::{trace, warn};
+use crate::{Block, ExtrinsicBlock};
+use crate::backend::ChainQuery;
+
+/// Import and block building errors.
+#[derive(Debug)]
+pub enum Error {
+	/// Backend error.
+	Backend(Box<dyn stderror::Error>),
+	/// Executor error.
+	Executor(Box<dyn stderror::Error>),
+	/// Block production deadline has passed.
+	DeadlineExceeded,
+	/// No undoable extrinsic has been applied.
+	NothingToUndo,
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl stderror::Error for Error { }
 
 /// An importer that can be shared across threads.
 pub struct MutexImporter<I> {
@@ -37,6 +71,11 @@ impl<I: BlockImporter> BlockImporter for MutexImporter<I> {
 	fn import_block(&mut self, block: Self::Block) -> Result<(), Self::Error> {
 		SharedBlockImporter::import_block(self, block)
 	}
+
+	fn pre_import_validation(&self, block: &Self::Block) -> Result<(), Self::Error> {
+		self.importer.lock().expect("Lock is poisoned")
+			.pre_import_validation(block)
+	}
 }
 
 impl<I: BlockImporter> SharedBlockImporter for MutexImporter<I> {
@@ -70,3 +109,453 @@ impl<I: RawImporter> SharedRawImporter for MutexImporter<I> {
 			.import_raw(raw)
 	}
 }
+
+/// A shared importer that limits the number of concurrent imports.
+/// Callers block until a permit is available.
+pub struct BackpressureImporter<I> {
+	importer: I,
+	permits: Arc<(Mutex<usize>, Condvar)>,
+}
+
+impl<I> BackpressureImporter<I> {
+	/// Create a new backpressure importer with the given number of permits.
+	pub fn new(importer: I, permits: NonZeroUsize) -> Self {
+		Self {
+			importer,
+			permits: Arc::new((Mutex::new(permits.get()), Condvar::new())),
+		}
+	}
+
+	/// Get the number of currently available permits.
+	pub fn available_permits(&self) -> usize {
+		*self.permits.0.lock().expect("Lock is poisoned")
+	}
+
+	fn acquire(&self) -> Permit<'_> {
+		let (available, condvar) = &*self.permits;
+		let mut available = available.lock().expect("Lock is poisoned");
+		while *available == 0 {
+			available = condvar.wait(available).expect("Lock is poisoned");
+		}
+		*available -= 1;
+
+		Permit { permits: &self.permits }
+	}
+}
+
+/// Import permit, released on drop, including when the import panics.
+struct Permit<'a> {
+	permits: &'a (Mutex<usize>, Condvar),
+}
+
+impl<'a> Drop for Permit<'a> {
+	fn drop(&mut self) {
+		let (available, condvar) = self.permits;
+		*available.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+		condvar.notify_one();
+	}
+}
+
+impl<I: Clone> Clone for BackpressureImporter<I> {
+	fn clone(&self) -> Self {
+		Self {
+			importer: self.importer.clone(),
+			permits: self.permits.clone(),
+		}
+	}
+}
+
+impl<I: SharedBlockImporter> BlockImporter for BackpressureImporter<I> {
+	type Block = I::Block;
+	type Error = I::Error;
+
+	fn import_block(&mut self, block: Self::Block) -> Result<(), Self::Error> {
+		SharedBlockImporter::import_block(self, block)
+	}
+
+	fn pre_import_validation(&self, block: &Self::Block) -> Result<(), Self::Error> {
+		self.importer.pre_import_validation(block)
+	}
+}
+
+impl<I: SharedBlockImporter> SharedBlockImporter for BackpressureImporter<I> {
+	fn import_block(
+		&self,
+		block: <Self as BlockImporter>::Block
+	) -> Result<(), <Self as BlockImporter>::Error> {
+		let _permit = self.acquire();
+		SharedBlockImporter::import_block(&self.importer, block)
+	}
+}
+
+type IdOf<B> = <B as Block>::Identifier;
+type IncludedAt<B> = Option<(IdOf<B>, usize)>;
+type ExtrinsicIndex<B> = HashMap<<B as ExtrinsicBlock>::ExtrinsicId, Vec<IdOf<B>>>;
+
+/// An importer that indexes extrinsics of imported blocks, so that the
+/// block including an extrinsic can be found.
+pub struct ExtrinsicIndexImporter<I: BlockImporter> where
+	I::Block: ExtrinsicBlock,
+{
+	importer: I,
+	index: Arc<RwLock<ExtrinsicIndex<I::Block>>>,
+}
+
+impl<I: BlockImporter> ExtrinsicIndexImporter<I> where
+	I::Block: ExtrinsicBlock,
+{
+	/// Create a new extrinsic indexing importer.
+	pub fn new(importer: I) -> Self {
+		Self {
+			importer,
+			index: Default::default(),
+		}
+	}
+
+	/// Find the canonical block including an extrinsic, and its depth.
+	pub fn find_block_by_extrinsic<Ba>(
+		&self,
+		backend: &Ba,
+		id: &<I::Block as ExtrinsicBlock>::ExtrinsicId,
+	) -> Result<IncludedAt<I::Block>, Ba::Error> where
+		Ba: ChainQuery<Block=I::Block>,
+	{
+		let hashes = self.index.read().expect("Lock is poisoned")
+			.get(id).cloned().unwrap_or_default();
+
+		for hash in hashes {
+			if backend.contains(&hash)? && backend.is_canon(&hash)? {
+				let depth = backend.depth_at(&hash)?;
+				return Ok(Some((hash, depth)))
+			}
+		}
+
+		Ok(None)
+	}
+
+	fn note_imported(
+		&self,
+		hash: IdOf<I::Block>,
+		extrinsics: Vec<<I::Block as ExtrinsicBlock>::Extrinsic>,
+	) {
+		let mut index = self.index.write().expect("Lock is poisoned");
+		for extrinsic in extrinsics {
+			let hashes = index.entry(I::Block::extrinsic_id(&extrinsic)).or_default();
+			if !hashes.contains(&hash) {
+				hashes.push(hash.clone());
+			}
+		}
+	}
+}
+
+impl<I: BlockImporter + Clone> Clone for ExtrinsicIndexImporter<I> where
+	I::Block: ExtrinsicBlock,
+{
+	fn clone(&self) -> Self {
+		Self {
+			importer: self.importer.clone(),
+			index: self.index.clone(),
+		}
+	}
+}
+
+impl<I: BlockImporter> BlockImporter for ExtrinsicIndexImporter<I> where
+	I::Block: ExtrinsicBlock,
+{
+	type Block = I::Block;
+	type Error = I::Error;
+
+	fn import_block(&mut self, block: Self::Block) -> Result<(), Self::Error> {
+		let hash = block.id();
+		let extrinsics = block.extrinsics();
+		self.importer.import_block(block)?;
+		self.note_imported(hash, extrinsics);
+		Ok(())
+	}
+
+	fn pre_import_validation(&self, block: &Self::Block) -> Result<(), Self::Error> {
+		self.importer.pre_import_validation(block)
+	}
+}
+
+impl<I: SharedBlockImporter> SharedBlockImporter for ExtrinsicIndexImporter<I> where
+	I::Block: ExtrinsicBlock,
+{
+	fn import_block(
+		&self,
+		block: <Self as BlockImporter>::Block
+	) -> Result<(), <Self as BlockImporter>::Error> {
+		let hash = block.id();
+		let extrinsics = block.extrinsics();
+		SharedBlockImporter::import_block(&self.importer, block)?;
+		self.note_imported(hash, extrinsics);
+		Ok(())
+	}
+}
+
+/// Import queue backed by a block importer. Network blocks whose parent is
+/// not yet known are kept, up to `max_orphans`, and imported once the parent
+/// is. Blocks failing import for any other reason are logged and dropped.
+pub struct QueuedImporter<I: BlockImporter, Ba> {
+	backend: Ba,
+	inner: Mutex<(I, Vec<I::Block>)>,
+	max_orphans: usize,
+}
+
+impl<I: BlockImporter, Ba> QueuedImporter<I, Ba> where
+	Ba: ChainQuery<Block=I::Block>,
+{
+	/// Create a new import queue from an importer, and a backend used to
+	/// check whether parents of queued blocks are known.
+	pub fn new(importer: I, backend: Ba, max_orphans: usize) -> Self {
+		Self {
+			backend,
+			inner: Mutex::new((importer, Vec::new())),
+			max_orphans,
+		}
+	}
+
+	fn is_parent_known(&self, block: &I::Block) -> bool {
+		match block.parent_id() {
+			Some(parent_id) => self.backend.contains(&parent_id).unwrap_or(false),
+			None => true,
+		}
+	}
+
+	fn import(importer: &mut I, block: I::Block) {
+		match importer.import_block(block) {
+			Ok(()) => trace!("Imported one block"),
+			Err(e) => warn!("Import block failed: {:?}", e),
+		}
+	}
+}
+
+impl<I, Ba> ImportQueue<I::Block> for QueuedImporter<I, Ba> where
+	I: BlockImporter + Send,
+	I::Block: Send,
+	Ba: ChainQuery<Block=I::Block> + Send + Sync,
+{
+	fn enqueue(&self, block: I::Block, origin: BlockOrigin) {
+		let mut inner = self.inner.lock().expect("Lock is poisoned");
+		let (importer, orphans) = &mut *inner;
+
+		if !self.is_parent_known(&block) {
+			match origin {
+				BlockOrigin::Local => warn!("Dropping local block with unknown parent"),
+				BlockOrigin::Network => {
+					let id = block.id();
+					if !orphans.iter().any(|orphan| orphan.id() == id) {
+						orphans.push(block);
+					}
+					while orphans.len() > self.max_orphans {
+						warn!("Too many orphan blocks, dropping the oldest");
+						orphans.remove(0);
+					}
+				},
+			}
+			return
+		}
+
+		Self::import(importer, block);
+
+		loop {
+			let (ready, waiting) = mem::take(orphans).into_iter()
+				.partition::<Vec<_>, _>(|orphan| self.is_parent_known(orphan));
+			*orphans = waiting;
+
+			if ready.is_empty() {
+				break
+			}
+			for block in ready {
+				Self::import(importer, block);
+			}
+		}
+	}
+
+	fn pending(&self) -> usize {
+		self.inner.lock().expect("Lock is poisoned").1.len()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use crate::backend::MemoryError;
+	use crate::test_support::{TestBlock, TestBackend, TestImporter, genesis_backend};
+
+	fn queue(max_orphans: usize) -> QueuedImporter<TestImporter, TestBackend> {
+		let backend = genesis_backend();
+		QueuedImporter::new(TestImporter::new(backend.clone()), backend, max_orphans)
+	}
+
+	#[test]
+	fn orphans_are_imported_once_parent_is_known() {
+		let queue = queue(16);
+		queue.enqueue(TestBlock::new(3, 2), BlockOrigin::Network);
+		queue.enqueue(TestBlock::new(2, 1), BlockOrigin::Network);
+		assert_eq!(queue.pending(), 2);
+
+		queue.enqueue(TestBlock::new(1, 0), BlockOrigin::Network);
+		assert_eq!(queue.pending(), 0);
+		assert_eq!(queue.backend.head(), 3);
+	}
+
+	#[test]
+	fn orphans_are_capped_and_not_retried() {
+		let queue = queue(10);
+		for id in 0..50 {
+			queue.enqueue(TestBlock::new(100 + id, 1000 + id), BlockOrigin::Network);
+		}
+		assert_eq!(queue.pending(), 10);
+		assert_eq!(queue.inner.lock().unwrap().0.calls, 0);
+	}
+
+	#[test]
+	fn failed_imports_are_dropped() {
+		let queue = queue(10);
+		queue.enqueue(TestBlock { id: 5, ..TestBlock::genesis() }, BlockOrigin::Network);
+		queue.enqueue(TestBlock::new(6, 42), BlockOrigin::Local);
+		assert_eq!(queue.pending(), 0);
+		assert_eq!(queue.inner.lock().unwrap().0.calls, 1);
+	}
+
+	#[test]
+	fn import_many_reports_results_in_submission_order() {
+		let backend = genesis_backend();
+		let mut importer = TestImporter::new(backend.clone());
+
+		let results = importer.import_many(vec![
+			TestBlock::new(1, 0),
+			TestBlock::new(5, 42),
+			TestBlock::new(2, 1),
+		]);
+		assert_eq!(results.len(), 3);
+		assert_eq!(results[0].as_ref().ok(), Some(&1));
+		assert!(matches!(results[1], Err(MemoryError::NotExist)));
+		assert_eq!(results[2].as_ref().ok(), Some(&2));
+		assert_eq!(backend.head(), 2);
+		assert!(!backend.contains(&5).unwrap());
+	}
+
+	#[test]
+	fn extrinsic_index_finds_canonical_block() {
+		let backend = genesis_backend();
+		let mut importer = ExtrinsicIndexImporter::new(TestImporter::new(backend.clone()));
+		let with_extrinsic = |id, parent| TestBlock { extrinsics: vec![7], ..TestBlock::new(id, parent) };
+
+		importer.import_block(with_extrinsic(1, 0)).unwrap();
+		importer.import_block(with_extrinsic(2, 0)).unwrap();
+		assert_eq!(importer.find_block_by_extrinsic(&backend, &7).unwrap(), Some((1, 1)));
+		assert_eq!(importer.find_block_by_extrinsic(&backend, &8).unwrap(), None);
+
+		importer.import_block(TestBlock::new(3, 2)).unwrap();
+		importer.import_block(TestBlock::new(4, 3)).unwrap();
+		assert_eq!(importer.find_block_by_extrinsic(&backend, &7).unwrap(), Some((2, 1)));
+	}
+
+	/// Importer counting validations, and rejecting blocks with odd ids.
+	struct ValidatingImporter {
+		importer: TestImporter,
+		validations: Arc<AtomicUsize>,
+	}
+
+	impl BlockImporter for ValidatingImporter {
+		type Block = TestBlock;
+		type Error = MemoryError;
+
+		fn import_block(&mut self, block: TestBlock) -> Result<(), MemoryError> {
+			self.pre_import_validation(&block)?;
+			self.importer.import_block(block)
+		}
+
+		fn pre_import_validation(&self, block: &TestBlock) -> Result<(), MemoryError> {
+			self.validations.fetch_add(1, Ordering::Relaxed);
+			if block.id % 2 == 1 {
+				return Err(MemoryError::InvalidOperation)
+			}
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn wrapped_importer_validates_once() {
+		let backend = genesis_backend();
+		let validations = Arc::new(AtomicUsize::new(0));
+		let importer = ValidatingImporter {
+			importer: TestImporter::new(backend.clone()),
+			validations: validations.clone(),
+		};
+		let queue = QueuedImporter::new(
+			BackpressureImporter::new(MutexImporter::new(importer), NonZeroUsize::new(1).unwrap()),
+			backend.clone(),
+			10,
+		);
+
+		queue.enqueue(TestBlock::new(2, 0), BlockOrigin::Network);
+		queue.enqueue(TestBlock::new(3, 2), BlockOrigin::Network);
+		assert_eq!(validations.load(Ordering::Relaxed), 2);
+		assert_eq!(backend.head(), 2);
+	}
+
+	#[test]
+	fn direct_import_is_validated() {
+		let backend = genesis_backend();
+		let validations = Arc::new(AtomicUsize::new(0));
+		let importer = ExtrinsicIndexImporter::new(BackpressureImporter::new(
+			MutexImporter::new(ValidatingImporter {
+				importer: TestImporter::new(backend.clone()),
+				validations: validations.clone(),
+			}),
+			NonZeroUsize::new(1).unwrap(),
+		));
+
+		assert!(SharedBlockImporter::import_block(&importer, TestBlock::new(3, 0)).is_err());
+		assert!(SharedBlockImporter::import_block(&importer, TestBlock::new(2, 0)).is_ok());
+		assert_eq!(validations.load(Ordering::Relaxed), 2);
+		assert_eq!(backend.head(), 2);
+	}
+
+	#[derive(Clone)]
+	struct PanicImporter;
+
+	impl BlockImporter for PanicImporter {
+		type Block = TestBlock;
+		type Error = MemoryError;
+
+		fn import_block(&mut self, block: TestBlock) -> Result<(), MemoryError> {
+			SharedBlockImporter::import_block(self, block)
+		}
+	}
+
+	impl SharedBlockImporter for PanicImporter {
+		fn import_block(&self, _block: TestBlock) -> Result<(), MemoryError> {
+			panic!("Import panicked")
+		}
+	}
+
+	#[test]
+	fn backpressure_permit_is_released_on_panic() {
+		let importer = BackpressureImporter::new(PanicImporter, NonZeroUsize::new(1).unwrap());
+		let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+			SharedBlockImporter::import_block(&importer, TestBlock::new(1, 0))
+		}));
+		assert!(result.is_err());
+		assert_eq!(importer.available_permits(), 1);
+	}
+
+	#[test]
+	fn backpressure_permits_are_returned_after_import() {
+		let backend = genesis_backend();
+		let importer = BackpressureImporter::new(
+			MutexImporter::new(TestImporter::new(backend.clone())),
+			NonZeroUsize::new(2).unwrap(),
+		);
+		assert_eq!(importer.available_permits(), 2);
+
+		SharedBlockImporter::import_block(&importer, TestBlock::new(1, 0)).unwrap();
+		SharedBlockImporter::import_block(&importer, TestBlock::new(2, 1)).unwrap();
+		assert_eq!(importer.available_permits(), 2);
+		assert_eq!(backend.head(), 2);
+	}
+}
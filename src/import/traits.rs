@@ -10,6 +10,28 @@ pub trait BlockImporter {
 
 	/// Commit a block into the backend, and handle consensus and auxiliary.
 	fn import_block(&mut self, block: Self::Block) -> Result<(), Self::Error>;
+
+	/// Cheap checks run before the block is executed, such as parent
+	/// linkage. Importers call this at the start of `import_block`, while
+	/// wrapping importers only delegate it to the inner importer, so that
+	/// each import validates once. Callers may also run it on its own to
+	/// reject a block early.
+	fn pre_import_validation(&self, _block: &Self::Block) -> Result<(), Self::Error> {
+		Ok(())
+	}
+
+	/// Validate and commit multiple blocks, returning one result per block
+	/// in submission order. Implementations may override this to import
+	/// atomically.
+	fn import_many(
+		&mut self,
+		blocks: Vec<Self::Block>,
+	) -> Vec<Result<<Self::Block as Block>::Identifier, Self::Error>> {
+		blocks.into_iter().map(|block| {
+			let id = block.id();
+			self.import_block(block).map(|()| id)
+		}).collect()
+	}
 }
 
 /// Shared block importer.
@@ -41,3 +63,21 @@ pub trait SharedRawImporter: RawImporter + Clone {
 		operation: Self::Operation
 	) -> Result<(), Self::Error>;
 }
+
+/// Origin of a block being imported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockOrigin {
+	/// Block is built locally.
+	Local,
+	/// Block is received from the network.
+	Network,
+}
+
+/// Object-safe handle of an import pipeline, so that it can be swapped at
+/// runtime.
+pub trait ImportQueue<B>: Send + Sync {
+	/// Queue a block for import.
+	fn enqueue(&self, block: B, origin: BlockOrigin);
+	/// Number of blocks queued but not yet imported.
+	fn pending(&self) -> usize;
+}
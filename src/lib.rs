@@ -5,3 +5,5 @@
 pub use blockchain_core::*;
 pub mod import;
 pub mod backend;
+#[cfg(any(test, feature = "test-helpers"))]
+pub mod test_support;
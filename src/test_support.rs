@@ -0,0 +1,384 @@
+//! Test fixtures and deterministic chain generation for tests and
+//! benchmarks.
+
+use std::{fmt, error as stderror};
+use std::collections::HashMap;
+use crate::{Block, ExtrinsicBlock, BlockExecutor, ExtrinsicBuilder, AsExternalities, NullExternalities};
+use crate::backend::{
+	Store, ChainQuery, SharedCommittable, SharedMemoryBackend, MemoryError,
+	Operation, ImportOperation,
+};
+use crate::import::{self, BlockImporter, BlockBuilder};
+
+/// Chain generation errors.
+#[derive(Debug)]
+pub enum Error<BE, IE> {
+	/// Backend error.
+	Backend(BE),
+	/// Importer error.
+	Import(IE),
+	/// Block builder error.
+	Build(import::Error),
+	/// No canonical block exists at the given depth.
+	UnknownDepth(usize),
+	/// No block is labeled with the given label.
+	UnknownLabel(String),
+}
+
+impl<BE: fmt::Debug, IE: fmt::Debug> fmt::Display for Error<BE, IE> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl<BE: fmt::Debug, IE: fmt::Debug> stderror::Error for Error<BE, IE> { }
+
+type GenerateResult<Ba, I> = Result<
+	<<Ba as Store>::Block as Block>::Identifier,
+	Error<<Ba as Store>::Error, <I as BlockImporter>::Error>,
+>;
+
+/// Generator building chains through a block builder and importer.
+///
+/// Every new block is built with `BlockBuilder` on top of its parent,
+/// applying the extrinsics given by `with_extrinsics`, and turned into a
+/// block by the `seal` function. Both are given a seed derived from the
+/// initial seed, so that the generated chain is deterministic.
+pub struct ChainGenerator<'a, Ba: ChainQuery, E: ExtrinsicBuilder, I, S> {
+	backend: &'a Ba,
+	executor: &'a E,
+	importer: I,
+	seal: S,
+	extrinsics: Box<dyn FnMut(usize, u64) -> Vec<E::Extrinsic> + 'a>,
+	seed: u64,
+	labels: HashMap<String, <Ba::Block as Block>::Identifier>,
+}
+
+impl<'a, Ba, E, I, S> ChainGenerator<'a, Ba, E, I, S> where
+	Ba: ChainQuery,
+	Ba::State: AsExternalities<E::Externalities>,
+	E: ExtrinsicBuilder<Block=Ba::Block>,
+	E::Inherent: Default,
+	I: BlockImporter<Block=Ba::Block>,
+	S: FnMut(E::BuildBlock, u64) -> Ba::Block,
+{
+	/// Create a new chain generator, building empty blocks. The seed given
+	/// to `seal` can be used to tell apart sibling blocks.
+	pub fn new(backend: &'a Ba, executor: &'a E, importer: I, seed: u64, seal: S) -> Self {
+		Self {
+			backend, executor, importer, seal, seed,
+			extrinsics: Box::new(|_, _| Vec::new()),
+			labels: HashMap::new(),
+		}
+	}
+
+	/// Set the extrinsics of new blocks, given the depth of the block and a
+	/// seed.
+	pub fn with_extrinsics<F>(mut self, extrinsics: F) -> Self where
+		F: FnMut(usize, u64) -> Vec<E::Extrinsic> + 'a,
+	{
+		self.extrinsics = Box::new(extrinsics);
+		self
+	}
+
+	/// Build `length` blocks on top of the current head, labeling the last
+	/// one.
+	pub fn linear(
+		&mut self,
+		label: &str,
+		length: usize,
+	) -> GenerateResult<Ba, I> {
+		let head = self.backend.head();
+		self.build_on(label, head, length)
+	}
+
+	/// Build `length` blocks on top of the canonical block at `depth`,
+	/// labeling the last one.
+	pub fn fork_at(
+		&mut self,
+		label: &str,
+		depth: usize,
+		length: usize,
+	) -> GenerateResult<Ba, I> {
+		let parent_id = self.backend.lookup_canon_depth(depth)
+			.map_err(Error::Backend)?
+			.ok_or(Error::UnknownDepth(depth))?;
+		self.build_on(label, parent_id, length)
+	}
+
+	/// Build `length` blocks on top of a labeled block, labeling the last
+	/// one.
+	pub fn extend(
+		&mut self,
+		label: &str,
+		from: &str,
+		length: usize,
+	) -> GenerateResult<Ba, I> {
+		let parent_id = self.label(from)
+			.ok_or_else(|| Error::UnknownLabel(from.to_string()))?;
+		self.build_on(label, parent_id, length)
+	}
+
+	/// Get the block hash of a label.
+	pub fn label(&self, label: &str) -> Option<<Ba::Block as Block>::Identifier> {
+		self.labels.get(label).cloned()
+	}
+
+	/// Get all labels and their block hashes.
+	pub fn labels(&self) -> &HashMap<String, <Ba::Block as Block>::Identifier> {
+		&self.labels
+	}
+
+	fn build_on(
+		&mut self,
+		label: &str,
+		parent_id: <Ba::Block as Block>::Identifier,
+		length: usize,
+	) -> GenerateResult<Ba, I> {
+		let mut parent_id = parent_id;
+		let mut depth = self.backend.depth_at(&parent_id).map_err(Error::Backend)?;
+
+		for _ in 0..length {
+			depth += 1;
+			let seed = self.next_seed();
+
+			let mut builder = BlockBuilder::new(
+				self.backend, self.executor, &parent_id, Default::default(),
+			).map_err(Error::Build)?;
+			for extrinsic in (self.extrinsics)(depth, seed) {
+				builder.apply_extrinsic(extrinsic).map_err(Error::Build)?;
+			}
+			let (build_block, _) = builder.finalize().map_err(Error::Build)?;
+
+			let block = (self.seal)(build_block, seed);
+			parent_id = block.id();
+			self.importer.import_block(block).map_err(Error::Import)?;
+		}
+
+		self.labels.insert(label.to_string(), parent_id.clone());
+		Ok(parent_id)
+	}
+
+	fn next_seed(&mut self) -> u64 {
+		// SplitMix64, so that seeds are well distributed but deterministic.
+		self.seed = self.seed.wrapping_add(SPLITMIX_GAMMA);
+		splitmix(self.seed)
+	}
+}
+
+const SPLITMIX_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// SplitMix64 output function. Unlike `DefaultHasher`, its output is fixed
+/// across Rust releases, so generated block ids are stable.
+fn splitmix(mut z: u64) -> u64 {
+	z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+	z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+	z ^ (z >> 31)
+}
+
+/// Block used by the test fixtures. Extrinsics are plain numbers, which
+/// are also their ids.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestBlock {
+	/// Block id.
+	pub id: u64,
+	/// Parent block id, None for genesis.
+	pub parent: Option<u64>,
+	/// Extrinsics of the block.
+	pub extrinsics: Vec<u64>,
+}
+
+impl TestBlock {
+	/// Genesis block, with id 0.
+	pub fn genesis() -> Self {
+		Self { id: 0, parent: None, extrinsics: Vec::new() }
+	}
+
+	/// Block without extrinsics on top of the given parent.
+	pub fn new(id: u64, parent: u64) -> Self {
+		Self { id, parent: Some(parent), extrinsics: Vec::new() }
+	}
+}
+
+impl Block for TestBlock {
+	type Identifier = u64;
+
+	fn id(&self) -> u64 { self.id }
+	fn parent_id(&self) -> Option<u64> { self.parent }
+}
+
+impl ExtrinsicBlock for TestBlock {
+	type Extrinsic = u64;
+	type ExtrinsicId = u64;
+
+	fn extrinsics(&self) -> Vec<u64> { self.extrinsics.clone() }
+	fn extrinsic_id(extrinsic: &u64) -> u64 { *extrinsic }
+}
+
+/// Memory backend of test blocks, without state or auxiliaries.
+pub type TestBackend = SharedMemoryBackend<TestBlock, (), ()>;
+
+/// Create a test backend holding only the genesis block.
+pub fn genesis_backend() -> TestBackend {
+	SharedMemoryBackend::new_with_genesis(TestBlock::genesis(), ())
+}
+
+/// Block being built by `TestExecutor`: the parent id and the extrinsics
+/// applied so far.
+pub type TestBuildBlock = (u64, Vec<u64>);
+
+/// Executor of test blocks, appending applied extrinsics to the block
+/// being built.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TestExecutor;
+
+impl BlockExecutor for TestExecutor {
+	type Error = fmt::Error;
+	type Block = TestBlock;
+	type Externalities = dyn NullExternalities;
+
+	fn execute_block(&self, _: &TestBlock, _: &mut Self::Externalities) -> Result<(), fmt::Error> {
+		Ok(())
+	}
+}
+
+impl ExtrinsicBuilder for TestExecutor {
+	type BuildBlock = TestBuildBlock;
+	type Inherent = ();
+	type Extrinsic = u64;
+
+	fn initialize_block(
+		&self,
+		parent_block: &TestBlock,
+		_: &mut Self::Externalities,
+		_: (),
+	) -> Result<TestBuildBlock, fmt::Error> {
+		Ok((parent_block.id, Vec::new()))
+	}
+
+	fn apply_extrinsic(
+		&self,
+		block: &mut TestBuildBlock,
+		extrinsic: u64,
+		_: &mut Self::Externalities,
+	) -> Result<(), fmt::Error> {
+		block.1.push(extrinsic);
+		Ok(())
+	}
+
+	fn finalize_block(&self, _: &mut TestBuildBlock, _: &mut Self::Externalities) -> Result<(), fmt::Error> {
+		Ok(())
+	}
+}
+
+/// Turn a built block into a test block, whose id is a hash of the
+/// parent, the extrinsics and the seed.
+pub fn seal((parent, extrinsics): TestBuildBlock, seed: u64) -> TestBlock {
+	let id = [parent, extrinsics.len() as u64].iter()
+		.chain(&extrinsics)
+		.chain(&[seed])
+		.fold(0u64, |id, value| splitmix(id.wrapping_add(SPLITMIX_GAMMA) ^ value));
+
+	TestBlock { id, parent: Some(parent), extrinsics }
+}
+
+/// Importer of test blocks, setting the head to the deepest block.
+pub struct TestImporter {
+	/// Backend blocks are imported into.
+	pub backend: TestBackend,
+	/// Number of `import_block` calls, including failed ones.
+	pub calls: usize,
+}
+
+impl TestImporter {
+	/// Create a new test importer.
+	pub fn new(backend: TestBackend) -> Self {
+		Self { backend, calls: 0 }
+	}
+}
+
+impl BlockImporter for TestImporter {
+	type Block = TestBlock;
+	type Error = MemoryError;
+
+	fn import_block(&mut self, block: TestBlock) -> Result<(), MemoryError> {
+		self.calls += 1;
+		self.pre_import_validation(&block)?;
+
+		let depth = match block.parent {
+			Some(parent) => self.backend.depth_at(&parent)? + 1,
+			None => 0,
+		};
+		let set_head = if depth > self.backend.depth_at(&self.backend.head())? {
+			Some(block.id)
+		} else {
+			None
+		};
+
+		self.backend.commit(Operation {
+			set_head,
+			import_block: vec![ImportOperation { block, state: () }],
+			..Default::default()
+		})
+	}
+}
+
+/// Chain generator of test blocks.
+pub type TestChainGenerator<'a> = ChainGenerator<
+	'a, TestBackend, TestExecutor, TestImporter, fn(TestBuildBlock, u64) -> TestBlock,
+>;
+
+/// Create a generator of empty test blocks on a test backend.
+pub fn test_generator(backend: &TestBackend, seed: u64) -> TestChainGenerator<'_> {
+	ChainGenerator::new(
+		backend,
+		&TestExecutor,
+		TestImporter::new(backend.clone()),
+		seed,
+		seal,
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn generate(seed: u64) -> (HashMap<String, u64>, u64) {
+		let backend = genesis_backend();
+		let mut generator = test_generator(&backend, seed)
+			.with_extrinsics(|depth, seed| vec![depth as u64, seed % 7]);
+
+		generator.linear("main", 5).unwrap();
+		generator.fork_at("fork", 2, 4).unwrap();
+		generator.extend("main2", "main", 2).unwrap();
+
+		(generator.labels().clone(), backend.head())
+	}
+
+	#[test]
+	fn same_seed_generates_same_chain() {
+		let (labels, head) = generate(42);
+
+		assert_eq!(labels.len(), 3);
+		assert_eq!(head, labels["main2"]);
+		assert_eq!(generate(42), (labels.clone(), head));
+		assert_ne!(generate(43).0, labels);
+	}
+
+	#[test]
+	fn seal_is_stable() {
+		// Pinned, so that a change of id generation is noticed.
+		assert_eq!(seal((0, vec![1, 2]), 3).id, 0x9197_f1ae_872d_28f7);
+	}
+
+	#[test]
+	fn blocks_contain_generated_extrinsics() {
+		let backend = genesis_backend();
+		let mut generator = test_generator(&backend, 0)
+			.with_extrinsics(|depth, _| vec![depth as u64; depth]);
+
+		let id = generator.linear("a", 3).unwrap();
+		assert_eq!(backend.block_at(&id).unwrap().extrinsics, vec![3, 3, 3]);
+		assert!(matches!(generator.extend("b", "missing", 1), Err(Error::UnknownLabel(_))));
+	}
+}